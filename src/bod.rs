@@ -0,0 +1,522 @@
+use super::*;
+use kif::{readdigit, PIECES};
+use position::{Move, Position};
+use sfen::*;
+
+/// a board diagram in BOD format.
+pub struct Bod {
+    /// the position.
+    pub sfen: Sfen,
+    /// sente's name from 先手 or 下手.
+    pub sname: Option<String>,
+    /// gote's name from 後手 or 上手.
+    pub gname: Option<String>,
+    /// title from the first comment line.
+    pub title: Option<String>,
+    /// the last move in the 手数 line.
+    pub lastmove: Option<LastMove>,
+    /// result in the 手数 line as a turn marker. fb, fw or d.
+    pub result: Option<String>,
+}
+
+/// read a piece in one letter like "歩" or "龍".
+///
+/// # Argument
+/// * `ch` - a letter.
+/// # Return value
+/// type of the piece and promoted or not.
+fn readkoma(ch: char) -> Option<(KomaType, bool)> {
+    let name = ch.to_string();
+    PIECES
+        .iter()
+        .find(|(nm, _, _)| *nm == name)
+        .map(|(_, k, p)| (*k, *p))
+}
+
+/// read a number like "十二", "２" or "12".
+///
+/// # Argument
+/// * `txt` - number. 1 if empty.
+fn readnum(txt: &str) -> Option<usize> {
+    if txt.is_empty() {
+        return Some(1);
+    }
+    if let Ok(num) = txt.parse() {
+        return Some(num);
+    }
+    let (tens, ones) = match txt.strip_prefix('十') {
+        Some(rest) => (10, rest),
+        None => (0, txt),
+    };
+    let mut chars = ones.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) if tens > 0 => Some(tens),
+        (Some(ch), None) => Some(tens + readdigit(ch)?),
+        _ => None,
+    }
+}
+
+/// read pieces in hand like "飛　角二　歩十二" or "なし".
+///
+/// # Argument
+/// * `txt` - pieces.
+/// # Return value
+/// types and numbers of the pieces or None.
+pub fn readhand(txt: &str) -> Option<Vec<(KomaType, usize)>> {
+    let txt: String = txt.chars().filter(|ch| !ch.is_whitespace()).collect();
+    let mut ret = Vec::new();
+    if txt == "なし" {
+        return Some(ret);
+    }
+    let mut rest = txt.as_str();
+    while let Some(ch) = rest.chars().next() {
+        let koma = match readkoma(ch)? {
+            (k, false) if k != KomaType::Gyoku => k,
+            _ => return None,
+        };
+        rest = &rest[ch.len_utf8()..];
+        let end = rest.find(|ch| readkoma(ch).is_some()).unwrap_or(rest.len());
+        ret.push((koma, readnum(&rest[..end])?));
+        rest = &rest[end..];
+    }
+    Some(ret)
+}
+
+/// read a row of the board like "v香v桂 ・ ・ ・ ・ ・v桂v香" between "|"s.
+///
+/// # Argument
+/// * `txt` - pieces from 9 to 1. "v" for gote's piece and "・" for a blank cell.
+/// # Return value
+/// 9 pieces or None.
+pub fn readrow(txt: &str) -> Option<Vec<Koma>> {
+    let mut ret = Vec::new();
+    let mut gote = false;
+    for ch in txt.chars() {
+        match ch {
+            _ if ch.is_whitespace() => continue,
+            'v' | 'V' if !gote => {
+                gote = true;
+                continue;
+            }
+            '・' if !gote => ret.push(Koma::from(' ', Promotion::None)),
+            _ => {
+                let (koma, promoted) = readkoma(ch)?;
+                let promotion = if promoted {
+                    Promotion::Promoted
+                } else {
+                    Promotion::None
+                };
+                let teban = if gote { Teban::Gote } else { Teban::Sente };
+                ret.push(Koma::new(koma, promotion, teban));
+            }
+        }
+        gote = false;
+    }
+    (ret.len() == 9 && !gote).then_some(ret)
+}
+
+/// Returns the cell the last move came from.
+/// the position before the move is made w/ each blank cell and
+/// the cell is taken if the move from there is written w/ the same direction letters.
+///
+/// # Arguments
+/// * `pos` - position after the move.
+/// * `to` - cell the piece went to.
+/// * `promoted` - the piece was promoted or not before the move.
+/// * `promote` - the piece was promoted by the move or not.
+/// * `dir` - direction letters.
+/// # Return value
+/// the cell, (0, 0) for a drop w/o 打 or None if it can't be known.
+fn findfrom(
+    pos: &Position,
+    to: (usize, usize),
+    promoted: bool,
+    promote: bool,
+    dir: &str,
+) -> Option<(usize, usize)> {
+    let cells: Vec<(usize, usize)> = (1..=9)
+        .flat_map(|suji| (1..=9).map(move |dan| (suji, dan)))
+        .filter(|&c| {
+            let mv = Move {
+                from: Some(c),
+                to,
+                drop: KomaType::Aki,
+                promote,
+            };
+            pos.koma(c).is_blank()
+                && pos.unmove(&mv).is_some_and(|before| {
+                    before.can_reach(c, to) && before.lastmove(&mv).dir == dir
+                })
+        })
+        .collect();
+    match cells[..] {
+        [from] => Some(from),
+        [] if !promoted && !promote && dir.is_empty() => Some((0, 0)),
+        _ => None,
+    }
+}
+
+/// read the last move like "▲７六歩まで", "△３三角成(77)  まで" or "▲同　銀まで".
+///
+/// # Arguments
+/// * `txt` - move after the number in the 手数 line.
+/// * `pos` - position after the move.
+/// # Return value
+/// LastMove or None. `to` is (0, 0) for 同.
+fn readlastmove(txt: &str, pos: &Position) -> Option<LastMove> {
+    let mut chars = txt.chars();
+    let mark = chars.next()?;
+    let teban = ki2::MARKS.iter().find(|(m, _)| *m == mark)?.1;
+    let rest = chars.as_str().trim_start();
+    let mut chars = rest.chars();
+    let to = match chars.next()? {
+        '同' => None,
+        ch => Some((readdigit(ch)?, readdigit(chars.next()?)?)),
+    };
+    let rest = chars.as_str().trim_start();
+    let (koma, promoted, rest) = PIECES
+        .iter()
+        .find_map(|(nm, k, p)| rest.strip_prefix(nm).map(|r| (*k, *p, r)))?;
+    let (dir, rest) = ki2::readdir(rest);
+    let rest = rest.trim_end().trim_end_matches("まで").trim_end();
+    let (rest, from) = match rest.strip_suffix(')').and_then(|r| r.rsplit_once('(')) {
+        Some((rest, from)) => {
+            let c: Vec<char> = from.chars().collect();
+            if c.len() != 2 {
+                return None;
+            }
+            (rest, Some((readdigit(c[0])?, readdigit(c[1])?)))
+        }
+        None => (rest, None),
+    };
+    let (promote, drop) = match rest.trim() {
+        "" => (Promotion::None, false),
+        "成" => (Promotion::Promoted, false),
+        "不成" => (Promotion::NotPromoted, false),
+        "打" => (Promotion::None, true),
+        _ => return None,
+    };
+    let mut lm = LastMove::new();
+    lm.teban = teban;
+    let to = match to {
+        Some(to) => to,
+        None => return Some(lm),
+    };
+    let k = pos.koma(to);
+    if k.teban() != teban
+        || k.komatype() != koma
+        || k.promotion().is_promoted() != (promoted || promote.is_promoted())
+    {
+        return None;
+    }
+    lm.from = match from {
+        Some(from) => from,
+        None if drop => (0, 0),
+        None => findfrom(pos, to, promoted, promote.is_promoted(), &dir).unwrap_or(FROMUNKNOWN),
+    };
+    lm.to = to;
+    lm.koma = Koma::new(
+        koma,
+        if promoted {
+            Promotion::Promoted
+        } else {
+            Promotion::None
+        },
+        teban,
+    );
+    lm.promote = promote;
+    lm.dir = dir;
+    Some(lm)
+}
+
+/// read a board diagram in BOD format. the inverse of `Sfen::dump`.
+///
+/// names (先手, 後手, 下手, 上手), hands (先手の持駒 and so on), 9 rows of the board,
+/// "手数＝N　▲７六歩まで" or a result like 引き分けです。 and 先手番 or 後手番 are read.
+/// the first comment starting w/ * is the title. other header lines are ignored.
+///
+/// # Argument
+/// * `txt` - BOD text.
+/// # Return value
+/// Bod or SfenError.
+pub fn read(txt: &str) -> Result<Bod, SfenError> {
+    let mut pos = Position::empty();
+    let mut rows = 0;
+    let mut sname = None;
+    let mut gname = None;
+    let mut title = None;
+    let mut turn = None;
+    let mut nteme = 1;
+    // line number, line and the move in the 手数 line. read after the board.
+    let mut tesuu = None;
+    for (i, line) in txt.lines().enumerate() {
+        let lineno = i + 1;
+        let err = |expected| SfenError::Bod {
+            line: lineno,
+            found: line.to_string(),
+            expected,
+        };
+        let org = line;
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with(['#', '+'])
+            || line.starts_with(|ch| readdigit(ch).is_some())
+        {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('*') {
+            let comment = comment.trim();
+            if title.is_none() && !comment.is_empty() {
+                title = Some(comment.to_string());
+            }
+            continue;
+        }
+        if let Some(row) = line.strip_prefix('|') {
+            if rows == 9 {
+                return Err(err("9 rows of the board"));
+            }
+            let row = row.split('|').next().unwrap_or_default();
+            let komas = readrow(row).ok_or_else(|| err("a row like |v香v桂 ・ ・ ・ 歩|一"))?;
+            rows += 1;
+            for (idx, koma) in komas.into_iter().enumerate() {
+                pos.put((9 - idx, rows), koma);
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("手数") {
+            let rest = rest.trim_start_matches(['＝', '=']).trim_start();
+            let end = rest
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(rest.len());
+            nteme = rest[..end].parse().map_err(|_| err("手数＝(number)"))?;
+            tesuu = Some((lineno, org, rest[end..].trim()));
+            continue;
+        }
+        match line {
+            "先手番" | "下手番" => turn = Some("b"),
+            "後手番" | "上手番" => turn = Some("w"),
+            _ => match line.split_once('：').or_else(|| line.split_once(':')) {
+                Some((key, val)) => {
+                    let val = val.trim();
+                    let hand = |teban| match readhand(val) {
+                        Some(hand) => Ok((teban, hand)),
+                        None => Err(err("pieces in hand like 飛　角二　歩十二 or なし")),
+                    };
+                    let hand = match key.trim().replace("持ち駒", "持駒").as_str() {
+                        "先手" | "下手" => {
+                            sname = Some(val.to_string());
+                            None
+                        }
+                        "後手" | "上手" => {
+                            gname = Some(val.to_string());
+                            None
+                        }
+                        "先手の持駒" | "下手の持駒" => Some(hand(Teban::Sente)?),
+                        "後手の持駒" | "上手の持駒" => Some(hand(Teban::Gote)?),
+                        _ => None,
+                    };
+                    if let Some((teban, hand)) = hand {
+                        for (koma, num) in hand {
+                            let num = pos.tegoma(teban, koma) + num;
+                            pos.set_tegoma(teban, koma, num);
+                        }
+                    }
+                }
+                None => return Err(err("a row, hands, names or 手数＝N")),
+            },
+        }
+    }
+    if rows != 9 {
+        return Err(SfenError::Bod {
+            line: txt.lines().count(),
+            found: format!("{} rows", rows),
+            expected: "9 rows of the board",
+        });
+    }
+    let mut lastmove = None;
+    let mut result = None;
+    if let Some((lineno, line, mvtxt)) = tesuu {
+        // turns and results written by `Sfen::dump`.
+        let written = ["b", "w", "fb", "fw", "d"]
+            .into_iter()
+            .find(|t| turnexp(t) == Some(mvtxt));
+        let t = match written {
+            _ if mvtxt.is_empty() => None,
+            Some(t @ ("b" | "w")) => Some(t),
+            Some(t) => {
+                result = Some(t.to_string());
+                None
+            }
+            None => match readlastmove(mvtxt, &pos) {
+                Some(lm) => {
+                    let t = if lm.teban == Teban::Sente { "w" } else { "b" };
+                    lastmove = Some(lm).filter(|lm| lm.is_ok());
+                    Some(t)
+                }
+                None => {
+                    return Err(SfenError::Bod {
+                        line: lineno,
+                        found: line.to_string(),
+                        expected: "a move like ▲７六歩まで which fits the board",
+                    })
+                }
+            },
+        };
+        turn = turn.or(t);
+    }
+    let sfen = pos.to_sfen();
+    let fields: Vec<&str> = sfen.split(' ').collect();
+    let sfen = Sfen::new(&format!(
+        "{} {} {} {}",
+        fields[0],
+        turn.unwrap_or("b"),
+        fields[2],
+        nteme
+    ))?;
+    Ok(Bod {
+        sfen,
+        sname,
+        gname,
+        title,
+        lastmove,
+        result,
+    })
+}
+
+#[test]
+fn bodtest() {
+    // Sfen::dump is read back.
+    // sfen, last move and the cell it came from.
+    let cases = [
+        (
+            "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1",
+            "",
+            (0, 0),
+        ),
+        (
+            "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3",
+            "3334FU",
+            (3, 3),
+        ),
+        (
+            "lnsgkgs1l/1r5b1/ppppppnpp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 2",
+            "2133KE",
+            (2, 1),
+        ),
+        // a tokin can come from many cells.
+        (
+            "4k4/9/4+P4/9/9/9/9/9/4K4 w G2r2b3g4s4n4l17p 10",
+            "5453TO",
+            FROMUNKNOWN,
+        ),
+        (
+            "4k4/9/9/4B4/9/9/9/9/4K4 w G2r2b3g4s4n4l17p 10",
+            "0054KA",
+            (0, 0),
+        ),
+        ("3gkg3/9/9/9/9/9/9/4G4/3G5 w - 10", "4958KIR", (4, 9)),
+    ];
+    for (sfen, lm, from) in cases {
+        let lastmove = if lm.is_empty() {
+            LastMove::new()
+        } else {
+            LastMove::read(lm).unwrap()
+        };
+        let mut lastmove = lastmove;
+        lastmove.teban = if sfen.contains(" b ") {
+            Teban::Gote
+        } else if lm.is_empty() {
+            Teban::None
+        } else {
+            Teban::Sente
+        };
+        let dump = |sfen: &Sfen| {
+            sfen.dump("先手さん", "後手さん", "タイトル", lastmove.clone(), None)
+                .unwrap()
+        };
+        let txt = dump(&Sfen::new(sfen).unwrap());
+        let bod = read(&txt).unwrap();
+        assert_eq!(dump(&bod.sfen), txt);
+        assert_eq!(bod.sname, Some(String::from("先手さん")));
+        assert_eq!(bod.gname, Some(String::from("後手さん")));
+        assert_eq!(bod.title, Some(String::from("タイトル")));
+        match bod.lastmove {
+            Some(lm) => {
+                assert_eq!(lm.from, from, "{}", txt);
+                assert_eq!(lm.to, lastmove.to);
+                assert_eq!(lm.to_string(), lastmove.to_string());
+            }
+            None => assert!(lm.is_empty()),
+        }
+    }
+
+    // turns and results written by dump are read.
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 w - 10").unwrap();
+    for turn in ["b", "w", "fb", "fw", "d"] {
+        let txt = sfen.dump("", "", "", LastMove::new(), Some(turn)).unwrap();
+        let bod = read(&txt).unwrap();
+        let result = ["fb", "fw", "d"].contains(&turn).then_some(turn);
+        assert_eq!(bod.result.as_deref(), result, "{}", txt);
+        let dumped = bod
+            .sfen
+            .dump("", "", "", LastMove::new(), bod.result.as_deref())
+            .unwrap();
+        assert_eq!(dumped, txt);
+    }
+
+    // a diagram from a forum w/ full-width spaces and (77).
+    let txt = "後手の持駒：飛　角　金二　歩十二　\n\
+        \x20 ９ ８ ７ ６ ５ ４ ３ ２ １\n\
+        +---------------------------+\n\
+        |v香v桂 ・ ・v玉 ・ ・v桂v香|一\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|二\n\
+        |　・　・　・　・ 龍　・　・　・　・|三\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|四\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|五\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|六\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|七\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|八\n\
+        | 香 桂 銀 ・ 玉 ・ 銀 桂 香|九\n\
+        +---------------------------+\n\
+        先手の持駒：銀二　金　歩六\n\
+        手数＝31  ▲５三飛成(58)  まで\n\
+        \n\
+        後手番\n";
+    let bod = read(txt).unwrap();
+    assert_eq!(
+        Position::from_sfen(&bod.sfen).unwrap().to_sfen(),
+        "ln2k2nl/9/4+R4/9/9/9/9/9/LNS1K1SNL w G2S6Prb2g12p 31"
+    );
+    assert_eq!(bod.sname, None);
+    assert_eq!(bod.title, None);
+    let lm = bod.lastmove.unwrap();
+    assert_eq!(lm.from, (5, 8));
+    assert_eq!(lm.to_string().unwrap(), "▲５三飛成まで");
+
+    // the cell the piece came from can't be known.
+    let txt = txt.replace("(58)", "");
+    let lm = read(&txt).unwrap().lastmove.unwrap();
+    assert_eq!(lm.from, FROMUNKNOWN);
+    assert_eq!(lm.frompos(), None);
+    assert_eq!(lm.to_string().unwrap(), "▲５三飛成まで");
+    let txt = txt.replace("▲５三飛成", "▲同　飛成");
+    assert!(read(&txt).unwrap().lastmove.is_none());
+
+    let errs = [
+        ("|v香v桂 ・ ・v玉 ・ ・v桂|一", 1),
+        ("|v香v桂 ・ ・v玉 ・ ・v桂vv|一", 1),
+        ("後手の持駒：玉", 1),
+        ("先手の持駒：歩十十", 1),
+        ("何か", 1),
+        ("手数＝", 1),
+        ("| ・ ・ ・ ・ ・ ・ ・ ・ ・|一", 1),
+    ];
+    for (txt, line) in errs {
+        match read(txt) {
+            Err(SfenError::Bod { line: l, .. }) => assert_eq!(l, line, "{}", txt),
+            ret => panic!("{} {:?}", txt, ret.map(|b| b.title)),
+        }
+    }
+    let txt = "| ・ ・ ・ ・ ・ ・ ・ ・ ・|一\n".repeat(9) + "手数＝1 ▲７六歩まで";
+    assert!(matches!(read(&txt), Err(SfenError::Bod { line: 10, .. })));
+}
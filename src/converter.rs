@@ -0,0 +1,296 @@
+/// placeholders in templates.
+/// * `{bg}` - background color.
+/// * `{format}` - output format. always png.
+/// * `{width}`, `{height}` - image size.
+/// * `{input}` - svg file. svg is given through stdin if not used.
+/// * `{output}` - png file. png is read from stdout if not used.
+const PLACEHOLDERS: [&str; 6] = ["bg", "format", "width", "height", "input", "output"];
+
+/// built-in converters. name and template.
+const BUILTINS: [(&str, &str); 5] = [
+    (
+        "rsvg",
+        "rsvg-convert --format={format} [-b {bg}] [--width={width} --height={height}]",
+    ),
+    (
+        "inkscape11",
+        "inkscape --pipe --export-filename=- --export-type={format} [-b {bg}] \
+        [--export-width={width} --export-height={height}]",
+    ),
+    (
+        "inkscape092",
+        "inkscape -z {input} [-b {bg}] [-w {width} -h {height}] -e {output}",
+    ),
+    (
+        "imagemagick",
+        "convert [-background {bg}] svg:- [-resize {width}x{height}!] {format}:-",
+    ),
+    (
+        "cairosvg",
+        "cairosvg - -f {format} [--output-width {width} --output-height {height}] -o -",
+    ),
+];
+
+/// a word in a template.
+#[derive(Clone, Debug, PartialEq)]
+enum Word {
+    /// always used.
+    Arg(String),
+    /// words in brackets. used only if all the placeholders in them have values.
+    Group(Vec<String>),
+}
+
+/// a converter defined as a command line template.
+///
+/// words are separated by spaces and placeholders like `{bg}` are replaced.
+/// words in brackets are dropped when a placeholder in them has no value.
+/// ex. `rsvg-convert --format={format} [-b {bg}] [--width={width} --height={height}]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    /// name to select.
+    pub name: String,
+    /// words of the command line.
+    words: Vec<Word>,
+}
+
+impl Template {
+    /// read a template.
+    ///
+    /// # Arguments
+    /// * `name` - name of the converter.
+    /// * `txt` - template. ex. `convert [-background {bg}] svg:- {format}:-`
+    /// # Return value
+    /// Template or error message.
+    pub fn read(name: &str, txt: &str) -> Result<Template, String> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("invalid converter name \"{}\".", name));
+        }
+        let mut words = Vec::new();
+        let mut group: Option<Vec<String>> = None;
+        for word in txt.split_whitespace() {
+            let (open, word) = match word.strip_prefix('[') {
+                Some(w) => (true, w),
+                None => (false, word),
+            };
+            let (close, word) = match word.strip_suffix(']') {
+                Some(w) => (true, w),
+                None => (false, word),
+            };
+            if open || word.starts_with('[') {
+                if group.is_some() || word.starts_with('[') {
+                    return Err(format!("nested brackets in \"{}\".", txt));
+                }
+                if words.is_empty() {
+                    return Err(format!("command is optional in \"{}\".", txt));
+                }
+                group = Some(Vec::new());
+            }
+            for ph in placeholders(word) {
+                if !PLACEHOLDERS.contains(&ph) {
+                    return Err(format!("unknown placeholder {{{}}} in \"{}\".", ph, txt));
+                }
+                if group.is_some() && (ph == "input" || ph == "output") {
+                    return Err(format!("{{{}}} in brackets in \"{}\".", ph, txt));
+                }
+            }
+            match group.as_mut() {
+                Some(g) => {
+                    if !word.is_empty() {
+                        g.push(word.to_string());
+                    }
+                }
+                None if close => return Err(format!("unbalanced brackets in \"{}\".", txt)),
+                None => words.push(Word::Arg(word.to_string())),
+            }
+            if close {
+                words.push(Word::Group(group.take().unwrap()));
+            }
+        }
+        if group.is_some() {
+            return Err(format!("unbalanced brackets in \"{}\".", txt));
+        }
+        if words.is_empty() {
+            return Err(format!("no command for converter {}.", name));
+        }
+        Ok(Template {
+            name: name.to_string(),
+            words,
+        })
+    }
+
+    /// read a definition.
+    ///
+    /// # Argument
+    /// * `txt` - name and template. ex. `magick=convert svg:- {format}:-`
+    /// # Return value
+    /// Template or error message.
+    pub fn define(txt: &str) -> Result<Template, String> {
+        match txt.split_once('=') {
+            Some((name, tmpl)) => Template::read(name.trim(), tmpl),
+            None => Err(format!("\"{}\" is not NAME=TEMPLATE.", txt)),
+        }
+    }
+
+    /// check if a placeholder is used.
+    ///
+    /// # Argument
+    /// * `name` - placeholder name w/o braces. ex. input
+    pub fn uses(&self, name: &str) -> bool {
+        self.words.iter().any(|w| match w {
+            Word::Arg(arg) => placeholders(arg).contains(&name),
+            Word::Group(args) => args.iter().any(|arg| placeholders(arg).contains(&name)),
+        })
+    }
+
+    /// make a command line.
+    ///
+    /// # Argument
+    /// * `values` - values for placeholders.
+    ///   placeholders w/o values are replaced w/ empty text outside brackets.
+    /// # Return value
+    /// command and args.
+    pub fn expand(&self, values: &[(&str, String)]) -> Vec<String> {
+        // returns the word and if all the placeholders had values.
+        let fill = |arg: &str| -> (String, bool) {
+            let mut ret = arg.to_string();
+            let mut complete = true;
+            for ph in placeholders(arg) {
+                let val = match values.iter().find(|(nm, _)| *nm == ph) {
+                    Some((_, val)) => val.as_str(),
+                    None => {
+                        complete = false;
+                        ""
+                    }
+                };
+                ret = ret.replace(&format!("{{{}}}", ph), val);
+            }
+            (ret, complete)
+        };
+        let mut ret = Vec::new();
+        for w in self.words.iter() {
+            match w {
+                Word::Arg(arg) => ret.push(fill(arg).0),
+                Word::Group(args) => {
+                    let args: Vec<(String, bool)> = args.iter().map(|a| fill(a)).collect();
+                    if args.iter().all(|(_, complete)| *complete) {
+                        ret.extend(args.into_iter().map(|(arg, _)| arg));
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// Returns names in braces.
+///
+/// # Argument
+/// * `word` - a word in a template.
+fn placeholders(word: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut rest = word;
+    while let Some(start) = rest.find('{') {
+        match rest[start..].find('}') {
+            Some(end) => {
+                ret.push(&rest[start + 1..start + end]);
+                rest = &rest[start + end + 1..];
+            }
+            None => break,
+        }
+    }
+    ret
+}
+
+/// Returns built-in converters.
+pub fn builtins() -> Vec<Template> {
+    BUILTINS
+        .iter()
+        .map(|(name, tmpl)| Template::read(name, tmpl).unwrap())
+        .collect()
+}
+
+/// read definitions from a file.
+/// a line is `NAME=TEMPLATE`. empty lines and lines starting w/ `#` are skipped.
+///
+/// # Argument
+/// * `txt` - content of the file.
+/// # Return value
+/// Templates or error message.
+pub fn read_definitions(txt: &str) -> Result<Vec<Template>, String> {
+    txt.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Template::define)
+        .collect()
+}
+
+#[test]
+fn templatetest() {
+    let all = builtins();
+    assert_eq!(all.len(), BUILTINS.len());
+    let rsvg = &all[0];
+    assert_eq!(rsvg.name, "rsvg");
+    assert_eq!(
+        rsvg.expand(&[("format", String::from("png"))]),
+        vec!["rsvg-convert", "--format=png"]
+    );
+    assert_eq!(
+        rsvg.expand(&[
+            ("format", String::from("png")),
+            ("bg", String::from("rgb(1, 2, 3)")),
+            ("width", String::from("520")),
+            ("height", String::from("550")),
+        ]),
+        vec![
+            "rsvg-convert",
+            "--format=png",
+            "-b",
+            "rgb(1, 2, 3)",
+            "--width=520",
+            "--height=550"
+        ]
+    );
+    assert!(!rsvg.uses("input"));
+    let old = all.iter().find(|t| t.name == "inkscape092").unwrap();
+    assert!(old.uses("input"));
+    assert!(old.uses("output"));
+    assert_eq!(
+        old.expand(&[
+            ("input", String::from("a.svg")),
+            ("output", String::from("a.png")),
+            ("width", String::from("26")),
+        ]),
+        vec!["inkscape", "-z", "a.svg", "-e", "a.png"]
+    );
+    let magick = all.iter().find(|t| t.name == "imagemagick").unwrap();
+    assert_eq!(
+        magick.expand(&[
+            ("format", String::from("png")),
+            ("width", String::from("26")),
+            ("height", String::from("27")),
+        ]),
+        vec!["convert", "svg:-", "-resize", "26x27!", "png:-"]
+    );
+
+    let tmpl = Template::define("mine = my-conv [ -b {bg} ] --size={width} -").unwrap();
+    assert_eq!(tmpl.name, "mine");
+    assert_eq!(tmpl.expand(&[]), vec!["my-conv", "--size=", "-"]);
+    assert_eq!(
+        tmpl.expand(&[("bg", String::from("red"))]),
+        vec!["my-conv", "-b", "red", "--size=", "-"]
+    );
+    assert!(Template::define("no template").is_err());
+    assert!(Template::define("x=").is_err());
+    assert!(Template::define("x=[conv]").is_err());
+    assert!(Template::define("x=conv [-b {bg}").is_err());
+    assert!(Template::define("x=conv -b {bg}]").is_err());
+    assert!(Template::define("x=conv [[-b {bg}]]").is_err());
+    assert!(Template::define("x=conv {color}").is_err());
+    assert!(Template::define("x=conv [{input}]").is_err());
+    assert!(Template::define("a b=conv").is_err());
+
+    let defs = read_definitions("# comment\n\nmine=my-conv -\ncairo=cairosvg - -o -\n").unwrap();
+    assert_eq!(defs.len(), 2);
+    assert_eq!(defs[1].name, "cairo");
+    assert!(read_definitions("mine=my-conv {x}").is_err());
+}
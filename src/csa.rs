@@ -0,0 +1,500 @@
+use super::*;
+use kif::Kifu;
+use position::{Move, Position, STARTPOS};
+use sfen::*;
+
+/// number of pieces of each type in a game. used for AL.
+const ALLPIECES: [(KomaType, usize); 7] = [
+    (KomaType::Fu, 18),
+    (KomaType::Kyosha, 4),
+    (KomaType::Keima, 4),
+    (KomaType::Gin, 4),
+    (KomaType::Kin, 4),
+    (KomaType::Kaku, 2),
+    (KomaType::Hisha, 2),
+];
+
+/// read a cell like "77". "00" is a hand.
+///
+/// # Argument
+/// * `txt` - 2 digits.
+/// # Return value
+/// Some(None) for a hand, Some(Some(cell)) for a cell or None.
+fn readcell(txt: &[char]) -> Option<Option<(usize, usize)>> {
+    let suji = txt.first()?.to_digit(10)? as usize;
+    let dan = txt.get(1)?.to_digit(10)? as usize;
+    match (suji, dan) {
+        (0, 0) => Some(None),
+        (1..=9, 1..=9) => Some(Some((suji, dan))),
+        _ => None,
+    }
+}
+
+/// read a piece like "FU".
+///
+/// # Argument
+/// * `txt` - 2 letters.
+fn readkoma(txt: &[char]) -> Option<Koma> {
+    Koma::fromcsa(&txt.get(0..2)?.iter().collect::<String>())
+}
+
+/// read a move like "+7776FU".
+///
+/// # Arguments
+/// * `txt` - move.
+/// * `pos` - position before the move.
+/// # Return value
+/// Move or None.
+fn readmove(txt: &str, pos: &Position) -> Option<Move> {
+    let c: Vec<char> = txt.chars().collect();
+    if c.len() != 7 {
+        return None;
+    }
+    let teban = match c[0] {
+        '+' => Teban::Sente,
+        '-' => Teban::Gote,
+        _ => return None,
+    };
+    if teban != pos.teban() {
+        return None;
+    }
+    let to = readcell(&c[3..5])??;
+    let koma = readkoma(&c[5..7])?;
+    match readcell(&c[1..3])? {
+        None => {
+            if koma.promotion().is_promoted() || koma.komatype() == KomaType::Gyoku {
+                return None;
+            }
+            Some(Move {
+                from: None,
+                to,
+                drop: koma.komatype(),
+                promote: false,
+            })
+        }
+        Some(from) => {
+            let k = pos.koma(from);
+            if k.komatype() != koma.komatype()
+                || (k.promotion().is_promoted() && !koma.promotion().is_promoted())
+            {
+                return None;
+            }
+            Some(Move {
+                from: Some(from),
+                to,
+                drop: KomaType::Aki,
+                promote: koma.promotion().is_promoted() && !k.promotion().is_promoted(),
+            })
+        }
+    }
+}
+
+/// read a move like "+7776FU" or "+8822UM" which leads to a position.
+/// the piece before the move is found in the position.
+/// a promoted piece is taken as promoted by the move if it could be.
+///
+/// # Arguments
+/// * `sfen` - position after the move.
+/// * `txt` - move.
+/// # Return value
+/// None if `txt` is not in CSA format, LastMove or SfenError.
+pub fn lastmove_after(sfen: &Sfen, txt: &str) -> Option<Result<LastMove, SfenError>> {
+    let c: Vec<char> = txt.chars().collect();
+    if c.len() != 7 {
+        return None;
+    }
+    let teban = match c[0] {
+        '+' => Teban::Sente,
+        '-' => Teban::Gote,
+        _ => return None,
+    };
+    let from = readcell(&c[1..3])?;
+    let to = readcell(&c[3..5])??;
+    let koma = readkoma(&c[5..7])?;
+    let after = match Position::from_sfen(sfen) {
+        Ok(pos) => pos,
+        Err(err) => return Some(Err(err)),
+    };
+    let zone = |dan: usize| {
+        if teban == Teban::Sente {
+            dan <= 3
+        } else {
+            dan >= 7
+        }
+    };
+    let moves = match from {
+        None => vec![Move {
+            from: None,
+            to,
+            drop: koma.komatype(),
+            promote: false,
+        }],
+        Some(from) => [true, false]
+            .iter()
+            .filter(|&&promote| {
+                !promote
+                    || (koma.promotion().is_promoted()
+                        && koma.komatype().is_promotable()
+                        && (zone(from.1) || zone(to.1)))
+            })
+            .map(|&promote| Move {
+                from: Some(from),
+                to,
+                drop: KomaType::Aki,
+                promote,
+            })
+            .collect(),
+    };
+    let target = after.koma(to);
+    let found = target.komatype() == koma.komatype()
+        && target.promotion().is_promoted() == koma.promotion().is_promoted()
+        && target.teban() == teban;
+    let lm = moves.iter().filter(|_| found).find_map(|mv| {
+        let before = after.unmove(mv)?;
+        match mv.from {
+            Some(from) if !before.can_reach(from, to) => None,
+            _ => Some(before.lastmove(mv)),
+        }
+    });
+    Some(lm.ok_or_else(|| SfenError::LastMove {
+        offset: 0,
+        found: txt.to_string(),
+        expected: "a move which leads to the position",
+    }))
+}
+
+/// Returns the result as a turn marker.
+///
+/// # Arguments
+/// * `txt` - result like %TORYO.
+/// * `teban` - player to move.
+/// # Return value
+/// fb, fw, d or None for unknown or suspended games.
+fn readresult(txt: &str, teban: Teban) -> Option<String> {
+    let (winner, loser) = if teban == Teban::Sente {
+        ("fb", "fw")
+    } else {
+        ("fw", "fb")
+    };
+    let ret = match txt {
+        "%TORYO" | "%TSUMI" | "%TIME_UP" | "%ILLEGAL_MOVE" => loser,
+        "%KACHI" => winner,
+        "%SENNICHITE" | "%JISHOGI" | "%HIKIWAKE" | "%MAX_MOVES" => "d",
+        "%+ILLEGAL_ACTION" => "fw",
+        "%-ILLEGAL_ACTION" => "fb",
+        _ => return None,
+    };
+    Some(String::from(ret))
+}
+
+/// read a game record in CSA format.
+///
+/// names (N+, N-), information ($EVENT, $START_TIME), the position (PI, P1-P9, P+, P-),
+/// the first player (+ or -), moves like +7776FU and results like %TORYO are read.
+/// the names are put as 先手 and 後手, $EVENT as 棋戦 and $START_TIME as 開始日時.
+/// only the first game is read if there are more games separated by "/".
+///
+/// # Argument
+/// * `txt` - CSA text.
+/// # Return value
+/// Kifu or SfenError.
+pub fn read(txt: &str) -> Result<Kifu, SfenError> {
+    let mut kifu = Kifu::new();
+    let mut pos: Option<Position> = None;
+    // the first player is given and moves can be read.
+    let mut started = false;
+    for (i, line) in txt.lines().enumerate() {
+        let lineno = i + 1;
+        let err = |expected| SfenError::Kifu {
+            line: lineno,
+            found: line.to_string(),
+            expected,
+        };
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('\'') || line.starts_with('V') {
+            continue;
+        }
+        if line == "/" {
+            break;
+        }
+        if let Some(name) = line.strip_prefix("N+") {
+            kifu.headers.push((String::from("先手"), name.to_string()));
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("N-") {
+            kifu.headers.push((String::from("後手"), name.to_string()));
+            continue;
+        }
+        if let Some(info) = line.strip_prefix('$') {
+            let (key, val) = info.split_once(':').unwrap_or((info, ""));
+            let key = match key {
+                "EVENT" => "棋戦",
+                "START_TIME" => "開始日時",
+                _ => key,
+            };
+            kifu.headers.push((key.to_string(), val.to_string()));
+            continue;
+        }
+        if line.starts_with('P') {
+            if started {
+                return Err(err("moves after the position"));
+            }
+            let c: Vec<char> = line.chars().collect();
+            match c.get(1) {
+                Some('I') => {
+                    let mut p = Position::from_sfen(&Sfen::new(STARTPOS)?)?;
+                    for chunk in c[2..].chunks(4) {
+                        let cell = readcell(chunk).flatten();
+                        let koma = chunk.get(2..4).and_then(readkoma);
+                        match (cell, koma) {
+                            (Some(cell), Some(k)) if p.koma(cell).komatype() == k.komatype() => {
+                                p.put(cell, Koma::from(' ', Promotion::None))
+                            }
+                            _ => return Err(err("pieces to remove like PI82HI22KA")),
+                        }
+                    }
+                    pos = Some(p);
+                }
+                Some(ch @ '1'..='9') => {
+                    let dan = ch.to_digit(10).unwrap() as usize;
+                    let p = pos.get_or_insert_with(Position::empty);
+                    for (idx, suji) in (1..=9).rev().enumerate() {
+                        let cell: Vec<char> = c.iter().skip(2 + idx * 3).take(3).copied().collect();
+                        let teban = match cell.first() {
+                            Some('+') => Teban::Sente,
+                            Some('-') => Teban::Gote,
+                            _ => continue,
+                        };
+                        match readkoma(&cell[1..]) {
+                            Some(k) => {
+                                p.put((suji, dan), Koma::new(k.komatype(), k.promotion(), teban));
+                            }
+                            None => return Err(err("pieces like P1-KY-KE-GI-KI-OU-KI-GI-KE-KY")),
+                        }
+                    }
+                }
+                Some(sign @ ('+' | '-')) => {
+                    let teban = if *sign == '+' {
+                        Teban::Sente
+                    } else {
+                        Teban::Gote
+                    };
+                    let p = pos.get_or_insert_with(Position::empty);
+                    for chunk in c[2..].chunks(4) {
+                        let cell = readcell(chunk);
+                        let al = chunk.get(2..4) == Some(&['A', 'L']);
+                        let koma = chunk.get(2..4).and_then(readkoma);
+                        match (cell, koma) {
+                            (Some(None), _) if al => {
+                                for (k, total) in ALLPIECES {
+                                    let used = (1..=9)
+                                        .flat_map(|suji| (1..=9).map(move |dan| (suji, dan)))
+                                        .filter(|&cell| p.koma(cell).komatype() == k)
+                                        .count()
+                                        + p.tegoma(Teban::Sente, k)
+                                        + p.tegoma(Teban::Gote, k);
+                                    let num = p.tegoma(teban, k) + total.saturating_sub(used);
+                                    p.set_tegoma(teban, k, num);
+                                }
+                            }
+                            (Some(None), Some(k))
+                                if !k.promotion().is_promoted()
+                                    && k.komatype() != KomaType::Gyoku =>
+                            {
+                                let num = p.tegoma(teban, k.komatype()) + 1;
+                                p.set_tegoma(teban, k.komatype(), num);
+                            }
+                            (Some(Some(cell)), Some(k)) => {
+                                p.put(cell, Koma::new(k.komatype(), k.promotion(), teban))
+                            }
+                            _ => return Err(err("pieces like P+00KI55KA or P-00AL")),
+                        }
+                    }
+                }
+                _ => return Err(err("PI, P1 to P9, P+ or P-")),
+            }
+            continue;
+        }
+        for stmt in line.split(',') {
+            if stmt.starts_with('T') {
+                continue;
+            }
+            if !started {
+                let teban = match stmt {
+                    "+" => Teban::Sente,
+                    "-" => Teban::Gote,
+                    _ => return Err(err("+ or - for the first player")),
+                };
+                let p = match pos.as_mut() {
+                    Some(p) => p,
+                    None => return Err(err("the position before the first player")),
+                };
+                p.set_teban(teban);
+                kifu.start = p.to_sfen();
+                // validate the position.
+                Position::from_sfen(&Sfen::new(&kifu.start)?)?;
+                started = true;
+                continue;
+            }
+            let p = pos.as_mut().unwrap();
+            if kifu.lines[0].finished {
+                return Err(err("nothing after the result"));
+            }
+            if stmt.starts_with('%') {
+                kifu.result = readresult(stmt, p.teban());
+                kifu.lines[0].finished = true;
+                continue;
+            }
+            let ply = kifu.lines[0].next();
+            match readmove(stmt, p) {
+                Some(mv) => {
+                    p.apply(&mv, ply)?;
+                    kifu.lines[0].moves.push(mv);
+                }
+                None => return Err(err("a move of the player to move like +7776FU")),
+            }
+        }
+    }
+    Ok(kifu)
+}
+
+#[test]
+fn csatest() {
+    let txt = "' sample\n\
+        V2.2\n\
+        N+o-jill\n\
+        N-ぢる\n\
+        $EVENT:test\n\
+        $START_TIME:2022/03/04 12:46:30\n\
+        PI\n\
+        +\n\
+        +7776FU\n\
+        T1\n\
+        -3334FU,T2\n\
+        +8822UM\n\
+        -3122GI\n\
+        +0045KA\n\
+        %TORYO\n";
+    let kifu = read(txt).unwrap();
+    assert_eq!(kifu.sname(), Some(String::from("o-jill")));
+    assert_eq!(kifu.gname(), Some(String::from("ぢる")));
+    assert_eq!(kifu.title(), Some(String::from("test 2022/03/04 12:46:30")));
+    let (sfen, lm) = kifu.position(0, None).unwrap();
+    let usi = |moves: &str| {
+        let (sfen, _) =
+            position::read_usi(&format!("position startpos moves {}", moves), None).unwrap();
+        Position::from_sfen(&sfen).unwrap().to_sfen()
+    };
+    assert_eq!(
+        Position::from_sfen(&sfen).unwrap().to_sfen(),
+        usi("7g7f 3c3d 8h2b+ 3a2b B*4e")
+    );
+    assert_eq!(lm.unwrap().to_string().unwrap(), "▲４五角打まで");
+    let (_, lm) = kifu.position(0, Some(3)).unwrap();
+    assert_eq!(lm.unwrap().to_string().unwrap(), "▲２二角成まで");
+    // gote resigned.
+    assert_eq!(kifu.turn(0, None), Some(String::from("fb")));
+    assert_eq!(kifu.turn(0, Some(5)), Some(String::from("fb")));
+    assert_eq!(kifu.turn(0, Some(4)), None);
+
+    let kifu = read("PI82HI22KA\n-\n-3334FU\n%TORYO").unwrap();
+    assert_eq!(
+        kifu.start,
+        "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+    );
+    assert_eq!(kifu.turn(0, None), Some(String::from("fw")));
+    assert_eq!(
+        read("PI\n+\n+7776FU\n%SENNICHITE").unwrap().result,
+        Some(String::from("d"))
+    );
+    assert_eq!(read("PI\n+\n%CHUDAN").unwrap().result, None);
+
+    let txt = "P1 *  *  *  * -OU *  *  *  * \n\
+        P2 *  *  *  *  *  *  *  *  * \n\
+        P3 *  *  *  * +TO *  *  *  * \n\
+        P+00KI\n\
+        P-00AL\n\
+        +\n\
+        +0052KI\n\
+        %TSUMI\n";
+    let kifu = read(txt).unwrap();
+    assert_eq!(kifu.start, "4k4/9/4+P4/9/9/9/9/9/9 b G2r2b3g4s4n4l17p 1");
+    let (sfen, _) = kifu.position(0, None).unwrap();
+    assert_eq!(
+        Position::from_sfen(&sfen).unwrap().to_sfen(),
+        "4k4/4G4/4+P4/9/9/9/9/9/9 w 2r2b3g4s4n4l17p 2"
+    );
+    assert_eq!(kifu.turn(0, None), Some(String::from("fb")));
+
+    let errs = [
+        ("PI82KA\n+", 1),
+        ("P1-XX\n+", 1),
+        ("P+99", 1),
+        ("PI\n+7776FU", 2),
+        ("+", 1),
+        ("PI\n+\n-3334FU", 3),
+        ("PI\n+\n+7776KI", 3),
+        ("PI\n+\n+7776FU\n%TORYO\n-3334FU", 5),
+        ("PI\n+\nP1 * ", 3),
+    ];
+    for (txt, line) in errs {
+        match read(txt) {
+            Err(SfenError::Kifu { line: l, .. }) => assert_eq!(l, line, "{}", txt),
+            ret => panic!("{} {:?}", txt, ret.map(|k| k.start)),
+        }
+    }
+    assert!(matches!(
+        read("PI\n+\n+7775FU"),
+        Err(SfenError::Move { ply: 1, .. })
+    ));
+
+    // the last move in CSA format is resolved in the position after it.
+    let lastmove = |sfen: &str, txt: &str| {
+        lastmove_after(&Sfen::new(sfen).unwrap(), txt)
+            .map(|ret| ret.map(|lm| lm.to_string().unwrap()))
+    };
+    let after = "lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2";
+    assert_eq!(
+        lastmove(after, "+7776FU"),
+        Some(Ok(String::from("▲７六歩まで")))
+    );
+    assert!(matches!(
+        lastmove(after, "-7776FU"),
+        Some(Err(SfenError::LastMove { .. }))
+    ));
+    assert_eq!(lastmove(after, "7g7f"), None);
+    assert_eq!(lastmove(after, "7776FU"), None);
+    let after = "lnsgkgsnl/1r5+B1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL w B 4";
+    assert_eq!(
+        lastmove(after, "+8822UM"),
+        Some(Ok(String::from("▲２二角成まで")))
+    );
+    let lm = lastmove_after(&Sfen::new(after).unwrap(), "+8822UM")
+        .unwrap()
+        .unwrap();
+    assert_eq!((lm.from, lm.to), ((8, 8), (2, 2)));
+    assert_eq!(lm.koma.komatype(), KomaType::Kaku);
+    assert!(!lm.koma.promotion().is_promoted());
+    assert_eq!(lm.promote, Promotion::Promoted);
+    let after = "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL b - 3";
+    assert_eq!(
+        lastmove(after, "-3334FU"),
+        Some(Ok(String::from("△３四歩まで")))
+    );
+    // exactly 2 digits for each cell and a piece.
+    for txt in [
+        "-33341FU", "-333FU", "-3304FU", "-3A34FU", "-3334XX", "-3334F",
+    ] {
+        assert_eq!(lastmove(after, txt), None, "{}", txt);
+    }
+    let golds = "4k4/9/9/9/9/9/9/4G4/3G5 w - 2";
+    assert_eq!(
+        lastmove(golds, "+4958KI"),
+        Some(Ok(String::from("▲５八金右まで")))
+    );
+    let drop = "4k4/9/9/9/4b4/9/9/9/4K4 b - 2";
+    assert_eq!(
+        lastmove(drop, "-0055KA"),
+        Some(Ok(String::from("△５五角打まで")))
+    );
+}
@@ -0,0 +1,423 @@
+use super::*;
+use sfen::*;
+
+/// glyphs for annotations. numbers from 1 to 20 are also accepted.
+const GLYPHS: [&str; 17] = [
+    "○", "×", "△", "▲", "□", "■", "◎", "●", "☆", "★", "!", "?", "!!", "??", "!?", "?!", "→",
+];
+
+/// how to draw the board and things over it.
+#[derive(Debug, Default)]
+pub struct Decoration {
+    /// seen from gote.
+    pub flip: bool,
+    /// colors.
+    pub theme: theme::Theme,
+    /// no background.
+    pub transparent: bool,
+    /// draw an arrow for the last move.
+    pub lmarrow: bool,
+    /// put the last move after the title.
+    pub caption: bool,
+    /// arrows.
+    pub arrows: Vec<Arrow>,
+    /// painted cells.
+    pub marks: Vec<Mark>,
+    /// glyphs on cells.
+    pub annotations: Vec<Annotation>,
+}
+
+/// an arrow on the board.
+#[derive(Debug, PartialEq)]
+pub struct Arrow {
+    /// cell index where the arrow starts. None for a piece in hand.
+    pub from: Option<(usize, usize)>,
+    /// piece in hand for a drop.
+    pub drop: KomaType,
+    /// cell index the arrow points.
+    pub to: (usize, usize),
+    /// color.
+    pub color: String,
+}
+
+impl Arrow {
+    /// read an arrow like "7g7f", "2h2d:red" or "P*5e:#00f".
+    ///
+    /// # Arguments
+    /// * `txt` - move in USI format and optional color after ':'.
+    /// * `index` - index in the arrow list. used for the error.
+    /// * `color` - color w/o one in `txt`.
+    /// # Return value
+    /// Arrow or SfenError.
+    pub fn read(txt: &str, index: usize, color: &str) -> Result<Arrow, SfenError> {
+        let (mv, color) = match txt.split_once(':') {
+            Some((mv, color)) => (mv, color),
+            None => (txt, color),
+        };
+        if !theme::is_color(color) {
+            return Err(SfenError::Arrow {
+                index,
+                found: color.to_string(),
+                expected: "a color like red, #f00 or #ff0000",
+            });
+        }
+        match position::Move::from_usi(mv, index) {
+            Ok(mv) => Ok(Arrow {
+                from: mv.from,
+                drop: mv.drop,
+                to: mv.to,
+                color: color.to_string(),
+            }),
+            Err(_) => Err(SfenError::Arrow {
+                index,
+                found: mv.to_string(),
+                expected: "USI move like 7g7f or P*5e",
+            }),
+        }
+    }
+}
+
+/// read arrows separated by ','.
+///
+/// # Arguments
+/// * `txt` - arrows like "7g7f,2h2d:red,P*5e:blue".
+/// * `color` - color of arrows w/o one. `Theme::arrow` usually.
+/// # Return value
+/// list of arrows or SfenError.
+pub fn read_arrows(txt: &str, color: &str) -> Result<Vec<Arrow>, SfenError> {
+    txt.split(',')
+        .filter(|a| !a.is_empty())
+        .enumerate()
+        .map(|(i, a)| Arrow::read(a, i + 1, color))
+        .collect()
+}
+
+/// a painted cell.
+#[derive(Debug, PartialEq)]
+pub struct Mark {
+    /// cell index.
+    pub pos: (usize, usize),
+    /// color.
+    pub color: String,
+    /// opacity. 0.0 to 1.0.
+    pub opacity: f64,
+}
+
+impl Mark {
+    /// read a mark like "55", "44:#f88" or "33:#8f8@0.5".
+    ///
+    /// # Arguments
+    /// * `txt` - (suji)(dan), optional color after ':' and optional opacity after '@'.
+    /// * `index` - index in the mark list. used for the error.
+    /// * `color` - color w/o one in `txt`.
+    /// # Return value
+    /// Mark or SfenError.
+    pub fn read(txt: &str, index: usize, color: &str) -> Result<Mark, SfenError> {
+        let err = |found: &str, expected| SfenError::Mark {
+            index,
+            found: found.to_string(),
+            expected,
+        };
+        let (txt, opacity) = match txt.split_once('@') {
+            Some((txt, op)) => match op.parse::<f64>() {
+                Ok(val) if (0.0..=1.0).contains(&val) => (txt, val),
+                _ => return Err(err(op, "opacity from 0 to 1")),
+            },
+            None => (txt, 1.0),
+        };
+        let (pos, color) = match txt.split_once(':') {
+            Some((pos, color)) => (pos, color),
+            None => (txt, color),
+        };
+        if !theme::is_color(color) {
+            return Err(err(color, "a color like red, #f00 or #ff0000"));
+        }
+        let pos = match readcell(pos) {
+            Some(pos) => pos,
+            None => return Err(err(pos, "a cell like 55")),
+        };
+        Ok(Mark {
+            pos,
+            color: color.to_string(),
+            opacity,
+        })
+    }
+}
+
+/// read marks separated by ','.
+///
+/// # Arguments
+/// * `txt` - marks like "55,44:#f88,33:#8f8@0.5".
+/// * `color` - color of marks w/o one. `Theme::mark` usually.
+/// # Return value
+/// list of marks or SfenError.
+pub fn read_marks(txt: &str, color: &str) -> Result<Vec<Mark>, SfenError> {
+    txt.split(',')
+        .filter(|m| !m.is_empty())
+        .enumerate()
+        .map(|(i, m)| Mark::read(m, i + 1, color))
+        .collect()
+}
+
+/// where to put a glyph in a cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Corner {
+    /// read "tl", "tr", "bl", "br" or "c".
+    pub fn from(txt: &str) -> Option<Corner> {
+        match txt {
+            "tl" => Some(Corner::TopLeft),
+            "tr" => Some(Corner::TopRight),
+            "bl" => Some(Corner::BottomLeft),
+            "br" => Some(Corner::BottomRight),
+            "c" => Some(Corner::Center),
+            _ => None,
+        }
+    }
+    /// Returns x and y in a cell, font size and text-anchor.
+    pub fn layout(self) -> (usize, usize, &'static str, &'static str) {
+        match self {
+            Corner::TopLeft => (1, 8, "9px", "start"),
+            Corner::TopRight => (19, 8, "9px", "end"),
+            Corner::BottomLeft => (1, 19, "9px", "start"),
+            Corner::BottomRight => (19, 19, "9px", "end"),
+            Corner::Center => (10, 15, "16px", "middle"),
+        }
+    }
+}
+
+/// a glyph on a cell.
+#[derive(Debug, PartialEq)]
+pub struct Annotation {
+    /// cell index.
+    pub pos: (usize, usize),
+    /// glyph.
+    pub glyph: String,
+    /// where to put in the cell.
+    pub corner: Corner,
+}
+
+impl Annotation {
+    /// read an annotation like "55:×", "44:1@tl" or "33:①@c".
+    ///
+    /// # Arguments
+    /// * `txt` - (suji)(dan):(glyph) and optional place after '@'. top right by default.
+    /// * `index` - index in the annotation list. used for the error.
+    /// # Return value
+    /// Annotation or SfenError.
+    pub fn read(txt: &str, index: usize) -> Result<Annotation, SfenError> {
+        let err = |found: &str, expected| SfenError::Annotation {
+            index,
+            found: found.to_string(),
+            expected,
+        };
+        let (txt, corner) = match txt.rsplit_once('@') {
+            Some((txt, c)) => match Corner::from(c) {
+                Some(corner) => (txt, corner),
+                None => return Err(err(c, "tl, tr, bl, br or c")),
+            },
+            None => (txt, Corner::TopRight),
+        };
+        let (pos, glyph) = match txt.split_once(':') {
+            Some((pos, glyph)) => (pos, glyph),
+            None => return Err(err(txt, "(cell):(glyph) like 55:×")),
+        };
+        let pos = match readcell(pos) {
+            Some(pos) => pos,
+            None => return Err(err(pos, "a cell like 55")),
+        };
+        if !is_glyph(glyph) {
+            return Err(err(glyph, "○×△▲□■◎●☆★!?→, 1 to 20 or ① to ⑳"));
+        }
+        Ok(Annotation {
+            pos,
+            glyph: glyph.to_string(),
+            corner,
+        })
+    }
+}
+
+/// read annotations separated by ','.
+///
+/// # Argument
+/// * `txt` - annotations like "55:×,44:1@tl,33:①@c".
+/// # Return value
+/// list of annotations or SfenError.
+pub fn read_annotations(txt: &str) -> Result<Vec<Annotation>, SfenError> {
+    txt.split(',')
+        .filter(|a| !a.is_empty())
+        .enumerate()
+        .map(|(i, a)| Annotation::read(a, i + 1))
+        .collect()
+}
+
+/// check if `txt` is a glyph for annotations.
+fn is_glyph(txt: &str) -> bool {
+    if GLYPHS.contains(&txt) {
+        return true;
+    }
+    if let Ok(num) = txt.parse::<u32>() {
+        return (1..=20).contains(&num) && !txt.starts_with('0');
+    }
+    let mut chars = txt.chars();
+    // ① to ⑳.
+    matches!(
+        (chars.next(), chars.next()),
+        (Some('\u{2460}'..='\u{2473}'), None)
+    )
+}
+
+/// read a cell like "55".
+fn readcell(txt: &str) -> Option<(usize, usize)> {
+    let cell: Vec<usize> = txt
+        .chars()
+        .filter_map(|ch| ch.to_digit(10).map(|d| d as usize))
+        .filter(|d| *d > 0)
+        .collect();
+    if cell.len() == 2 && txt.len() == 2 {
+        Some((cell[0], cell[1]))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn arrowtest() {
+    let arrows = read_arrows("7g7f,2h2d:red,P*5e:#00f", "#F80").unwrap();
+    assert_eq!(
+        arrows,
+        vec![
+            Arrow {
+                from: Some((7, 7)),
+                drop: KomaType::Aki,
+                to: (7, 6),
+                color: String::from("#F80")
+            },
+            Arrow {
+                from: Some((2, 8)),
+                drop: KomaType::Aki,
+                to: (2, 4),
+                color: String::from("red")
+            },
+            Arrow {
+                from: None,
+                drop: KomaType::Fu,
+                to: (5, 5),
+                color: String::from("#00f")
+            },
+        ]
+    );
+    assert!(read_arrows("", "#F80").unwrap().is_empty());
+    assert_eq!(
+        read_arrows("7g7f,7g7z", "#F80"),
+        Err(SfenError::Arrow {
+            index: 2,
+            found: String::from("7g7z"),
+            expected: "USI move like 7g7f or P*5e"
+        })
+    );
+    assert!(matches!(
+        read_arrows("7g7f:red\" onload=\"x", "#F80"),
+        Err(SfenError::Arrow { index: 1, .. })
+    ));
+}
+
+#[test]
+fn marktest() {
+    let marks = read_marks("55,44:#f88,33:#8f8@0.5", "#8CF").unwrap();
+    assert_eq!(
+        marks,
+        vec![
+            Mark {
+                pos: (5, 5),
+                color: String::from("#8CF"),
+                opacity: 1.0
+            },
+            Mark {
+                pos: (4, 4),
+                color: String::from("#f88"),
+                opacity: 1.0
+            },
+            Mark {
+                pos: (3, 3),
+                color: String::from("#8f8"),
+                opacity: 0.5
+            },
+        ]
+    );
+    assert_eq!(read_marks("11@0", "#8CF").unwrap()[0].opacity, 0.0);
+    assert!(matches!(
+        read_marks("55,05", "#8CF"),
+        Err(SfenError::Mark { index: 2, .. })
+    ));
+    assert!(matches!(
+        read_marks("555", "#8CF"),
+        Err(SfenError::Mark { index: 1, .. })
+    ));
+    assert!(matches!(
+        read_marks("55@1.5", "#8CF"),
+        Err(SfenError::Mark { index: 1, .. })
+    ));
+    assert!(matches!(
+        read_marks("55:url(x)", "#8CF"),
+        Err(SfenError::Mark { index: 1, .. })
+    ));
+}
+
+#[test]
+fn annotationtest() {
+    let anns = read_annotations("55:×,44:1@tl,33:①@c,22:20@br").unwrap();
+    assert_eq!(
+        anns,
+        vec![
+            Annotation {
+                pos: (5, 5),
+                glyph: String::from("×"),
+                corner: Corner::TopRight
+            },
+            Annotation {
+                pos: (4, 4),
+                glyph: String::from("1"),
+                corner: Corner::TopLeft
+            },
+            Annotation {
+                pos: (3, 3),
+                glyph: String::from("①"),
+                corner: Corner::Center
+            },
+            Annotation {
+                pos: (2, 2),
+                glyph: String::from("20"),
+                corner: Corner::BottomRight
+            },
+        ]
+    );
+    assert!(read_annotations("11:⑳,11:!?").is_ok());
+    assert!(matches!(
+        read_annotations("55:×,44:21"),
+        Err(SfenError::Annotation { index: 2, .. })
+    ));
+    assert!(matches!(
+        read_annotations("55:<b>"),
+        Err(SfenError::Annotation { index: 1, .. })
+    ));
+    assert!(matches!(
+        read_annotations("55:×@up"),
+        Err(SfenError::Annotation { index: 1, .. })
+    ));
+    assert!(matches!(
+        read_annotations("55"),
+        Err(SfenError::Annotation { index: 1, .. })
+    ));
+    assert!(matches!(
+        read_annotations("05:1"),
+        Err(SfenError::Annotation { index: 1, .. })
+    ));
+}
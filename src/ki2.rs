@@ -0,0 +1,257 @@
+use super::*;
+use kif::Kifu;
+use position::{Move, Position};
+use sfen::*;
+
+/// marks in front of moves. sente's or gote's.
+pub const MARKS: [(char, Teban); 4] = [
+    ('▲', Teban::Sente),
+    ('△', Teban::Gote),
+    ('☗', Teban::Sente),
+    ('☖', Teban::Gote),
+];
+
+/// words to tell pieces apart and the letters in `LastMove::dir`.
+const DIRECTIONS: [(char, char); 8] = [
+    ('右', 'R'),
+    ('左', 'L'),
+    ('上', 'U'),
+    ('行', 'U'),
+    ('入', 'U'),
+    ('引', 'H'),
+    ('寄', 'Y'),
+    ('直', 'C'),
+];
+
+/// read direction words like "右上" at the head.
+///
+/// # Argument
+/// * `txt` - text after the piece.
+/// # Return value
+/// the letters in `LastMove::dir` and the rest.
+pub fn readdir(txt: &str) -> (String, &str) {
+    let mut dir = String::new();
+    let mut rest = txt;
+    while let Some((ch, d)) = rest
+        .chars()
+        .next()
+        .and_then(|ch| DIRECTIONS.iter().find(|(w, _)| *w == ch))
+    {
+        dir.push(*d);
+        rest = &rest[ch.len_utf8()..];
+    }
+    (dir, rest)
+}
+
+/// read a move like "７六歩", "同　銀", "５八金右" or "２二角成" w/o the mark.
+/// the moving piece is found in the position.
+/// a piece in hand is dropped w/o 打 if no piece on the board can move there.
+///
+/// # Arguments
+/// * `txt` - move.
+/// * `prev` - cell the previous move went to. used for 同.
+/// * `pos` - position before the move.
+/// # Return value
+/// Move or None if the move is invalid or ambiguous.
+fn readmove(txt: &str, prev: Option<(usize, usize)>, pos: &Position) -> Option<Move> {
+    let mut chars = txt.chars();
+    let to = match chars.next()? {
+        '同' => prev?,
+        ch => (kif::readdigit(ch)?, kif::readdigit(chars.next()?)?),
+    };
+    let rest = chars.as_str().trim_start();
+    let (koma, promoted, rest) = kif::PIECES
+        .iter()
+        .find_map(|(nm, k, p)| rest.strip_prefix(nm).map(|r| (*k, *p, r)))?;
+    let (dir, rest) = readdir(rest);
+    let (promote, drop) = match rest {
+        "" | "不成" => (false, false),
+        "成" => (true, false),
+        "打" => (false, true),
+        _ => return None,
+    };
+    let origins = pos.origins(to, koma, promoted, &dir);
+    let inhand = !promoted && dir.is_empty() && pos.tegoma(pos.teban(), koma) > 0;
+    if drop || (origins.is_empty() && inhand) {
+        if !inhand || promote {
+            return None;
+        }
+        return Some(Move {
+            from: None,
+            to,
+            drop: koma,
+            promote: false,
+        });
+    }
+    match origins[..] {
+        [from] => Some(Move {
+            from: Some(from),
+            to,
+            drop: KomaType::Aki,
+            promote,
+        }),
+        _ => None,
+    }
+}
+
+/// read a game record in KI2 format.
+///
+/// header lines like "先手：name", moves like "▲７六歩　△３四歩" w/o the cells they come from
+/// and branches starting w/ "変化：3手" are read.
+/// comments and other lines starting w/ #, *, & or まで are skipped.
+///
+/// # Argument
+/// * `txt` - KI2 text.
+/// # Return value
+/// Kifu or SfenError.
+pub fn read(txt: &str) -> Result<Kifu, SfenError> {
+    let mut kifu = Kifu::new();
+    // position at the end of the last line. made again after headers and 変化.
+    let mut pos: Option<Position> = None;
+    for (i, line) in txt.lines().enumerate() {
+        let lineno = i + 1;
+        let err = |expected| SfenError::Kifu {
+            line: lineno,
+            found: line.to_string(),
+            expected,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', '*', '&']) || line.starts_with("まで") {
+            continue;
+        }
+        // (index of the mark, index of the move, player)
+        let marks: Vec<(usize, usize, Teban)> = line
+            .char_indices()
+            .filter_map(|(idx, ch)| {
+                MARKS
+                    .iter()
+                    .find(|(m, _)| *m == ch)
+                    .map(|(m, teban)| (idx, idx + m.len_utf8(), *teban))
+            })
+            .collect();
+        if marks.is_empty() {
+            match line.split_once('：').or_else(|| line.split_once(':')) {
+                Some((key, val)) => kifu.read_header(key.trim(), val.trim()).map_err(err)?,
+                None => return Err(err("a header, moves like ▲７六歩　△３四歩 or 変化")),
+            }
+            pos = None;
+            continue;
+        }
+        if marks[0].0 != 0 {
+            return Err(err("moves starting w/ ▲ or △"));
+        }
+        let branch = kifu.lines.len() - 1;
+        for (n, &(_, start, teban)) in marks.iter().enumerate() {
+            let end = marks.get(n + 1).map_or(line.len(), |(idx, _, _)| *idx);
+            let mvtxt = line[start..end].trim();
+            if kifu.lines[branch].finished {
+                break;
+            }
+            if kif::TERMINALS.iter().any(|t| mvtxt.starts_with(t)) {
+                kifu.lines[branch].finished = true;
+                break;
+            }
+            let p = match pos.as_mut() {
+                Some(p) => p,
+                None => {
+                    let (sfen, _) = kifu.position(branch, None)?;
+                    pos.insert(Position::from_sfen(&sfen)?)
+                }
+            };
+            if p.teban() != teban {
+                return Err(err("a move of the player to move"));
+            }
+            let ply = kifu.lines[branch].next();
+            match readmove(mvtxt, kifu.prev_to(), p) {
+                Some(mv) => {
+                    p.apply(&mv, ply)?;
+                    kifu.lines[branch].moves.push(mv);
+                }
+                None => return Err(err("a move like ▲７六歩, △同　銀 or ▲５八金右")),
+            }
+        }
+    }
+    Ok(kifu)
+}
+
+#[test]
+fn ki2test() {
+    let txt = "開始日時：2022/03/04 12:46:30\n\
+        棋戦：テスト棋戦\n\
+        手合割：平手\n\
+        先手：o-jill\n\
+        後手：ぢる\n\
+        \n\
+        ▲７六歩　　△３四歩　　▲２二角成　△同　銀　　▲４五角\n\
+        *comment\n\
+        △５二金右　▲６八銀　　△投了\n\
+        まで7手で先手の勝ち\n\
+        \n\
+        変化：4手\n\
+        △同　飛\n";
+    let kifu = read(txt).unwrap();
+    assert_eq!(kifu.sname(), Some(String::from("o-jill")));
+    assert_eq!(
+        kifu.title(),
+        Some(String::from("テスト棋戦 2022/03/04 12:46:30"))
+    );
+    assert_eq!(kifu.lines.len(), 2);
+    assert!(kifu.lines[0].finished);
+
+    let usi = |moves: &str| {
+        let (sfen, _) =
+            position::read_usi(&format!("position startpos moves {}", moves), None).unwrap();
+        Position::from_sfen(&sfen).unwrap().to_sfen()
+    };
+    let sfen = |(sfen, _): (Sfen, Option<LastMove>)| Position::from_sfen(&sfen).unwrap().to_sfen();
+    assert_eq!(
+        sfen(kifu.position(0, None).unwrap()),
+        usi("7g7f 3c3d 8h2b+ 3a2b B*4e 6a5b 7i6h")
+    );
+    assert_eq!(
+        sfen(kifu.position(1, None).unwrap()),
+        usi("7g7f 3c3d 8h2b+ 8b2b")
+    );
+    let (_, lm) = kifu.position(0, Some(6)).unwrap();
+    assert_eq!(lm.unwrap().to_string().unwrap(), "△５二金右まで");
+    let (_, lm) = kifu.position(0, Some(5)).unwrap();
+    assert_eq!(lm.unwrap().to_string().unwrap(), "▲４五角打まで");
+
+    // the moves printed by LastMove::to_string are read back.
+    let golds = "4k4/9/9/9/9/9/9/9/3GGG3 b - 1";
+    let pos = Position::from_sfen(&Sfen::new(golds).unwrap()).unwrap();
+    for usi in ["4i5h", "6i5h", "5i5h", "4i4h", "6i6h", "5i4h", "5i6h"] {
+        let mv = Move::from_usi(usi, 1).unwrap();
+        let txt = pos.lastmove(&mv).to_string().unwrap();
+        let txt = txt.trim_start_matches('▲').trim_end_matches("まで");
+        assert_eq!(readmove(txt, None, &pos), Some(mv), "{}", txt);
+    }
+    assert_eq!(readmove("５八金", None, &pos), None);
+    assert_eq!(
+        readmove("５八金右上", None, &pos),
+        Some(Move::from_usi("4i5h", 1).unwrap())
+    );
+
+    let kifu = read("手合割：香落ち\n△３四歩　▲７六歩\n").unwrap();
+    assert_eq!(
+        sfen(kifu.position(0, None).unwrap()),
+        "lnsgkgsn1/1r5b1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 3"
+    );
+
+    let errs = [
+        ("手合割：九枚落ち", 1),
+        ("▲７六歩\n▲３四歩", 2),
+        ("▲７六金", 1),
+        ("▲５八金", 1),
+        ("▲７六歩打", 1),
+        ("▲７六歩\n変化：2手", 2),
+        ("７六歩", 1),
+        ("+---------+", 1),
+    ];
+    for (txt, line) in errs {
+        match read(txt) {
+            Err(SfenError::Kifu { line: l, .. }) => assert_eq!(l, line, "{}", txt),
+            ret => panic!("{} {:?}", txt, ret.map(|k| k.start)),
+        }
+    }
+}
@@ -0,0 +1,674 @@
+use super::*;
+use bod::{readhand, readrow};
+use position::{Move, Position, STARTPOS};
+use sfen::*;
+
+/// handicaps in 手合割 and the starting positions.
+const HANDICAPS: [(&str, &str); 11] = [
+    ("平手", STARTPOS),
+    (
+        "香落ち",
+        "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "右香落ち",
+        "1nsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "角落ち",
+        "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "飛車落ち",
+        "lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "飛香落ち",
+        "lnsgkgsn1/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "二枚落ち",
+        "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "四枚落ち",
+        "1nsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "六枚落ち",
+        "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "八枚落ち",
+        "3gkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+    (
+        "十枚落ち",
+        "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1",
+    ),
+];
+
+/// names of pieces in moves. promoted or not.
+/// longer names come first not to be taken as a promotion.
+pub const PIECES: [(&str, KomaType, bool); 19] = [
+    ("成香", KomaType::Kyosha, true),
+    ("成桂", KomaType::Keima, true),
+    ("成銀", KomaType::Gin, true),
+    ("歩", KomaType::Fu, false),
+    ("香", KomaType::Kyosha, false),
+    ("桂", KomaType::Keima, false),
+    ("銀", KomaType::Gin, false),
+    ("金", KomaType::Kin, false),
+    ("角", KomaType::Kaku, false),
+    ("飛", KomaType::Hisha, false),
+    ("玉", KomaType::Gyoku, false),
+    ("王", KomaType::Gyoku, false),
+    ("と", KomaType::Fu, true),
+    ("杏", KomaType::Kyosha, true),
+    ("圭", KomaType::Keima, true),
+    ("全", KomaType::Gin, true),
+    ("馬", KomaType::Kaku, true),
+    ("龍", KomaType::Hisha, true),
+    ("竜", KomaType::Hisha, true),
+];
+
+/// words which end a game.
+pub const TERMINALS: [&str; 11] = [
+    "投了",
+    "中断",
+    "千日手",
+    "詰み",
+    "持将棋",
+    "切れ負け",
+    "反則勝ち",
+    "反則負け",
+    "入玉勝ち",
+    "不戦勝",
+    "不戦敗",
+];
+
+/// a sequence of moves in a game record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    /// ply of the first move. 1 for the main line.
+    pub start: usize,
+    /// index of the line this one branches from. 0 for the main line.
+    pub parent: usize,
+    /// moves.
+    pub moves: Vec<Move>,
+    /// no more moves after a word like 投了.
+    pub finished: bool,
+}
+
+impl Line {
+    /// Returns a line w/o moves.
+    ///
+    /// # Arguments
+    /// * `start` - ply of the first move.
+    /// * `parent` - index of the line this one branches from.
+    pub fn new(start: usize, parent: usize) -> Line {
+        Line {
+            start,
+            parent,
+            moves: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the ply of the next move.
+    pub fn next(&self) -> usize {
+        self.start + self.moves.len()
+    }
+}
+
+/// a game record.
+#[derive(Clone, Debug)]
+pub struct Kifu {
+    /// header fields in order. (key, value)
+    pub headers: Vec<(String, String)>,
+    /// starting position in sfen.
+    pub start: String,
+    /// the main line and branches in order of appearance.
+    pub lines: Vec<Line>,
+    /// result at the end of the main line. fb, fw or d as turn markers.
+    pub result: Option<String>,
+}
+
+impl Kifu {
+    /// Returns a game record from the initial position w/o moves.
+    pub fn new() -> Kifu {
+        Kifu {
+            headers: Vec::new(),
+            start: String::from(STARTPOS),
+            lines: vec![Line::new(1, 0)],
+            result: None,
+        }
+    }
+
+    /// Returns the value of a header field.
+    ///
+    /// # Argument
+    /// * `key` - name of the field. ex. 先手
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns sente's name from 先手 or 下手.
+    pub fn sname(&self) -> Option<String> {
+        self.header("先手")
+            .or_else(|| self.header("下手"))
+            .map(|s| s.to_string())
+    }
+
+    /// Returns gote's name from 後手 or 上手.
+    pub fn gname(&self) -> Option<String> {
+        self.header("後手")
+            .or_else(|| self.header("上手"))
+            .map(|s| s.to_string())
+    }
+
+    /// Returns title from 棋戦 and 開始日時.
+    pub fn title(&self) -> Option<String> {
+        let title = ["棋戦", "開始日時"]
+            .iter()
+            .filter_map(|k| self.header(k))
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        if title.is_empty() {
+            None
+        } else {
+            Some(title)
+        }
+    }
+
+    /// Returns the result as a turn marker if the position is at the end of the main line.
+    ///
+    /// # Arguments
+    /// * `branch` - index of the line. 0 for the main line.
+    /// * `ply` - number of moves to apply. all moves in the line when None.
+    pub fn turn(&self, branch: usize, ply: Option<usize>) -> Option<String> {
+        match ply {
+            _ if branch != 0 => None,
+            Some(ply) if ply < self.lines[0].moves.len() => None,
+            _ => self.result.clone(),
+        }
+    }
+
+    /// add a header field like 先手：name.
+    /// 手合割 sets the starting position and 変化：3手 starts a branch from the 3rd move.
+    /// the branch comes from the latest line w/ the ply.
+    ///
+    /// # Arguments
+    /// * `key` - name of the field.
+    /// * `val` - value.
+    /// # Return value
+    /// () or what was expected.
+    pub fn read_header(&mut self, key: &str, val: &str) -> Result<(), &'static str> {
+        match key {
+            "手合割" => match HANDICAPS.iter().find(|(nm, _)| *nm == val) {
+                Some((_, sfen)) => self.start = sfen.to_string(),
+                // the board diagram follows.
+                None if val == "その他" => {}
+                None => return Err("平手 or a handicap like 香落ち"),
+            },
+            "変化" => {
+                let ply = match val.trim_end_matches('手').parse::<usize>() {
+                    Ok(ply) if ply > 0 => ply,
+                    _ => return Err("変化：(ply)手"),
+                };
+                let parent = match self
+                    .lines
+                    .iter()
+                    .rposition(|l| l.start <= ply && ply < l.next())
+                {
+                    Some(parent) => parent,
+                    None => return Err("a branch from a ply in the moves above"),
+                };
+                self.lines.push(Line::new(ply, parent));
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.headers.push((key.to_string(), val.to_string()));
+        Ok(())
+    }
+
+    /// Returns the cell the previous move went to in the last line. used for 同.
+    pub fn prev_to(&self) -> Option<(usize, usize)> {
+        let cur = self.lines.last()?;
+        match cur.moves.last() {
+            Some(mv) => Some(mv.to),
+            None if cur.start > 1 => self.moves(cur.parent).get(cur.start - 2).map(|mv| mv.to),
+            None => None,
+        }
+    }
+
+    /// Returns the moves from the start to the end of a line.
+    ///
+    /// # Argument
+    /// * `branch` - index of the line. 0 for the main line.
+    pub fn moves(&self, branch: usize) -> Vec<Move> {
+        let line = &self.lines[branch];
+        if branch == 0 {
+            return line.moves.clone();
+        }
+        let mut moves = self.moves(line.parent);
+        moves.truncate(line.start - 1);
+        moves.extend(line.moves.iter().cloned());
+        moves
+    }
+
+    /// Returns the position after a ply.
+    ///
+    /// # Arguments
+    /// * `branch` - index of the line. 0 for the main line, 1 for the first 変化.
+    /// * `ply` - number of moves to apply. all moves in the line when None.
+    /// # Return value
+    /// position after the moves and the last applied move, or SfenError.
+    pub fn position(
+        &self,
+        branch: usize,
+        ply: Option<usize>,
+    ) -> Result<(Sfen, Option<LastMove>), SfenError> {
+        if branch >= self.lines.len() {
+            return Err(SfenError::Kifu {
+                line: 0,
+                found: format!("branch {}", branch),
+                expected: "a branch in the kifu",
+            });
+        }
+        let sfen = Sfen::new(&self.start)?;
+        let mut pos = Position::from_sfen(&sfen)?;
+        let mut lm = None;
+        let moves = self.moves(branch);
+        for (i, mv) in moves.iter().take(ply.unwrap_or(usize::MAX)).enumerate() {
+            let last = pos.lastmove(mv);
+            pos.apply(mv, i + 1)?;
+            lm = Some(last);
+        }
+        Ok((Sfen::new(&pos.to_sfen())?, lm))
+    }
+}
+
+/// read a digit in ascii, zenkaku or kanji.
+///
+/// # Argument
+/// * `ch` - 1 to 9.
+pub fn readdigit(ch: char) -> Option<usize> {
+    let idx = "123456789"
+        .chars()
+        .position(|c| c == ch)
+        .or_else(|| "１２３４５６７８９".chars().position(|c| c == ch))
+        .or_else(|| "一二三四五六七八九".chars().position(|c| c == ch))?;
+    Some(idx + 1)
+}
+
+/// read a move like "７六歩(77)", "同　角成(88)" or "５五角打".
+///
+/// # Arguments
+/// * `txt` - move. time and other texts after the move are ignored.
+/// * `prev` - cell the previous move went to. used for 同.
+/// # Return value
+/// Move, the piece and if it's promoted before the move, or None.
+fn readmove(txt: &str, prev: Option<(usize, usize)>) -> Option<(Move, KomaType, bool)> {
+    let mut chars = txt.chars();
+    let to = match chars.next()? {
+        '同' => prev?,
+        ch => (readdigit(ch)?, readdigit(chars.next()?)?),
+    };
+    let rest = chars.as_str().trim_start();
+    let (koma, promoted, rest) = PIECES
+        .iter()
+        .find_map(|(nm, k, p)| rest.strip_prefix(nm).map(|r| (*k, *p, r)))?;
+    let (promote, drop, rest) = if let Some(r) = rest.strip_prefix("不成") {
+        (false, false, r)
+    } else if let Some(r) = rest.strip_prefix('成') {
+        (true, false, r)
+    } else if let Some(r) = rest.strip_prefix('打') {
+        (false, true, r)
+    } else {
+        (false, false, rest)
+    };
+    let from = match rest.strip_prefix('(') {
+        Some(r) => {
+            let c: Vec<char> = r.chars().take(3).collect();
+            if c.len() != 3 || c[2] != ')' {
+                return None;
+            }
+            Some((readdigit(c[0])?, readdigit(c[1])?))
+        }
+        None => None,
+    };
+    let mv = match from {
+        Some(_) if drop => return None,
+        Some(from) => Move {
+            from: Some(from),
+            to,
+            drop: KomaType::Aki,
+            promote,
+        },
+        None if !drop || promoted || koma == KomaType::Gyoku => return None,
+        None => Move {
+            from: None,
+            to,
+            drop: koma,
+            promote: false,
+        },
+    };
+    Some((mv, koma, promoted))
+}
+
+/// Returns the starting position in a board diagram.
+///
+/// # Arguments
+/// * `diagram` - pieces on the board and in hands.
+/// * `rows` - number of rows read.
+/// * `turn` - b or w.
+/// * `line` - line number for the error.
+/// # Return value
+/// sfen or SfenError.
+fn boardsfen(
+    diagram: &Position,
+    rows: usize,
+    turn: &str,
+    line: usize,
+) -> Result<String, SfenError> {
+    if rows != 9 {
+        return Err(SfenError::Kifu {
+            line,
+            found: format!("{} rows", rows),
+            expected: "9 rows of the board",
+        });
+    }
+    let sfen = diagram.to_sfen();
+    let fields: Vec<&str> = sfen.split(' ').collect();
+    Ok(format!("{} {} {} 1", fields[0], turn, fields[2]))
+}
+
+/// read a game record in KIF format.
+///
+/// header lines like "先手：name", moves like "   1 ７六歩(77)"
+/// and branches starting w/ "変化：3手" are read.
+/// a board diagram in BOD format like the one w/ 手合割：その他 is the starting position.
+/// moves are applied to check the piece on the cell in ().
+/// comments and other lines starting w/ #, *, & or まで are skipped.
+///
+/// # Argument
+/// * `txt` - KIF text.
+/// # Return value
+/// Kifu or SfenError.
+pub fn read(txt: &str) -> Result<Kifu, SfenError> {
+    let mut kifu = Kifu::new();
+    // starting position in the board diagram, number of its rows and the turn.
+    let mut board = None;
+    let mut rows = 0;
+    let mut turn = "b";
+    // position in the current line to check moves.
+    let mut pos: Option<Position> = None;
+    for (i, line) in txt.lines().enumerate() {
+        let lineno = i + 1;
+        let err = |expected| SfenError::Kifu {
+            line: lineno,
+            found: line.to_string(),
+            expected,
+        };
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with(['#', '*', '&'])
+            || line.starts_with("手数")
+            || line.starts_with("まで")
+        {
+            continue;
+        }
+        if line.starts_with(|c: char| c.is_ascii_digit()) {
+            let (num, mvtxt) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mvtxt = mvtxt.trim_start();
+            let branch = kifu.lines.len() - 1;
+            let cur = &kifu.lines[branch];
+            if cur.finished {
+                continue;
+            }
+            if num.parse::<usize>().ok() != Some(cur.next()) {
+                return Err(err("consecutive move numbers"));
+            }
+            if TERMINALS.iter().any(|t| mvtxt.starts_with(t)) {
+                kifu.lines[branch].finished = true;
+                continue;
+            }
+            let (mv, koma, promoted) = match readmove(mvtxt, kifu.prev_to()) {
+                Some(ret) => ret,
+                None => return Err(err("a move like ７六歩(77), 同　歩(23) or ５五角打")),
+            };
+            if let Some(diagram) = board.take() {
+                kifu.start = boardsfen(&diagram, rows, turn, lineno)?;
+            }
+            let p = match pos.as_mut() {
+                Some(p) => p,
+                None => {
+                    let (sfen, _) = kifu.position(branch, None)?;
+                    pos.insert(Position::from_sfen(&sfen)?)
+                }
+            };
+            if let Some(from) = mv.from {
+                let k = p.koma(from);
+                if k.teban() != p.teban()
+                    || k.komatype() != koma
+                    || k.promotion().is_promoted() != promoted
+                {
+                    return Err(err("a move of the piece on the cell in ()"));
+                }
+            }
+            let ply = kifu.lines[branch].next();
+            p.apply(&mv, ply)?;
+            kifu.lines[branch].moves.push(mv);
+            continue;
+        }
+        // borders and column numbers of the board.
+        if line.starts_with('+') || line.starts_with(|ch| readdigit(ch).is_some()) {
+            continue;
+        }
+        if let Some(row) = line.strip_prefix('|') {
+            if rows == 9 {
+                return Err(err("9 rows of the board"));
+            }
+            let row = row.split('|').next().unwrap_or_default();
+            let komas = readrow(row).ok_or_else(|| err("a row like |v香v桂 ・ ・ ・ 歩|一"))?;
+            rows += 1;
+            let diagram = board.get_or_insert_with(Position::empty);
+            for (idx, koma) in komas.into_iter().enumerate() {
+                diagram.put((9 - idx, rows), koma);
+            }
+            continue;
+        }
+        match line {
+            "先手番" | "下手番" => turn = "b",
+            "後手番" | "上手番" => turn = "w",
+            _ => match line.split_once('：').or_else(|| line.split_once(':')) {
+                Some((key, val)) => {
+                    let (key, val) = (key.trim(), val.trim());
+                    let teban = match key.replace("持ち駒", "持駒").as_str() {
+                        "先手の持駒" | "下手の持駒" => Some(Teban::Sente),
+                        "後手の持駒" | "上手の持駒" => Some(Teban::Gote),
+                        _ => None,
+                    };
+                    match teban {
+                        Some(teban) => {
+                            let hand = readhand(val).ok_or_else(|| {
+                                err("pieces in hand like 飛　角二　歩十二 or なし")
+                            })?;
+                            let diagram = board.get_or_insert_with(Position::empty);
+                            for (koma, num) in hand {
+                                let num = diagram.tegoma(teban, koma) + num;
+                                diagram.set_tegoma(teban, koma, num);
+                            }
+                        }
+                        None => {
+                            kifu.read_header(key, val).map_err(err)?;
+                            // a new line starts from its own position.
+                            pos = None;
+                        }
+                    }
+                }
+                None => return Err(err("a header, a move or 変化")),
+            },
+        }
+    }
+    if let Some(diagram) = board {
+        kifu.start = boardsfen(&diagram, rows, turn, txt.lines().count())?;
+    }
+    Ok(kifu)
+}
+
+#[test]
+fn kiftest() {
+    let txt = "# ---- Kifu for Windows ----\r\n\
+        開始日時：2022/03/04 12:46:30\r\n\
+        棋戦：テスト棋戦\r\n\
+        手合割：平手\r\n\
+        先手：o-jill\r\n\
+        後手：ぢる\r\n\
+        手数----指手---------消費時間--\r\n\
+        \x20  1 ７六歩(77)   ( 0:00/00:00:00)\r\n\
+        *comment\r\n\
+        \x20  2 ３四歩(33)   ( 0:01/00:00:01)\r\n\
+        \x20  3 ２二角成(88)   ( 0:00/00:00:01)+\r\n\
+        \x20  4 同　銀(31)   ( 0:00/00:00:01)+\r\n\
+        \x20  5 ４五角打   ( 0:00/00:00:01)\r\n\
+        \x20  6 投了\r\n\
+        まで5手で先手の勝ち\r\n\
+        \r\n\
+        変化：4手\r\n\
+        \x20  4 同　飛(82)   ( 0:00/00:00:00)\r\n\
+        \r\n\
+        変化：3手\r\n\
+        \x20  3 ６六歩(67)   ( 0:00/00:00:00)\r\n\
+        \x20  4 ８四歩(83)   ( 0:00/00:00:00)+\r\n\
+        \r\n\
+        変化：4手\r\n\
+        \x20  4 ３五歩(34)   ( 0:00/00:00:00)\r\n\
+        \r\n\
+        変化：3手\r\n\
+        \x20  3 ２六歩(27)   ( 0:00/00:00:00)\r\n";
+    let kifu = read(txt).unwrap();
+    assert_eq!(kifu.sname(), Some(String::from("o-jill")));
+    assert_eq!(kifu.gname(), Some(String::from("ぢる")));
+    assert_eq!(
+        kifu.title(),
+        Some(String::from("テスト棋戦 2022/03/04 12:46:30"))
+    );
+    assert_eq!(kifu.lines.len(), 5);
+    assert_eq!(kifu.lines[1].parent, 0);
+    assert_eq!(kifu.lines[2].parent, 0);
+    assert_eq!(kifu.lines[3].parent, 2);
+    // the latest line w/ the ply. same moves before the ply as the main line.
+    assert_eq!(kifu.lines[4].parent, 2);
+
+    let usi = |moves: &str| {
+        let (sfen, _) =
+            position::read_usi(&format!("position startpos moves {}", moves), None).unwrap();
+        Position::from_sfen(&sfen).unwrap().to_sfen()
+    };
+    let sfen = |(sfen, _): (Sfen, Option<LastMove>)| Position::from_sfen(&sfen).unwrap().to_sfen();
+    assert_eq!(
+        sfen(kifu.position(0, None).unwrap()),
+        usi("7g7f 3c3d 8h2b+ 3a2b B*4e")
+    );
+    assert_eq!(
+        sfen(kifu.position(0, Some(3)).unwrap()),
+        usi("7g7f 3c3d 8h2b+")
+    );
+    assert_eq!(sfen(kifu.position(0, Some(0)).unwrap()), STARTPOS);
+    assert_eq!(
+        sfen(kifu.position(1, None).unwrap()),
+        usi("7g7f 3c3d 8h2b+ 8b2b")
+    );
+    assert_eq!(
+        sfen(kifu.position(2, None).unwrap()),
+        usi("7g7f 3c3d 6g6f 8c8d")
+    );
+    assert_eq!(
+        sfen(kifu.position(3, None).unwrap()),
+        usi("7g7f 3c3d 6g6f 3d3e")
+    );
+    assert_eq!(sfen(kifu.position(4, None).unwrap()), usi("7g7f 3c3d 2g2f"));
+    let (_, lm) = kifu.position(0, Some(4)).unwrap();
+    assert_eq!(lm.unwrap().to_string().unwrap(), "△２二銀まで");
+    let (_, lm) = kifu.position(0, None).unwrap();
+    assert_eq!(lm.unwrap().to_string().unwrap(), "▲４五角打まで");
+    assert!(matches!(
+        kifu.position(5, None),
+        Err(SfenError::Kifu { .. })
+    ));
+
+    let kifu = read("手合割：香落ち\n下手：a\n上手：b\n1 ３四歩(33)\n").unwrap();
+    assert_eq!(kifu.sname(), Some(String::from("a")));
+    assert_eq!(kifu.gname(), Some(String::from("b")));
+    assert_eq!(kifu.title(), None);
+    assert_eq!(
+        sfen(kifu.position(0, None).unwrap()),
+        "lnsgkgsn1/1r5b1/pppppp1pp/6p2/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 2"
+    );
+
+    let txt = "# ---- Kifu for Windows V7 ----\n\
+        手合割：その他\n\
+        後手の持駒：なし\n\
+        \x20 ９ ８ ７ ６ ５ ４ ３ ２ １\n\
+        +---------------------------+\n\
+        | ・ ・ ・ ・ ・ ・ ・v桂v香|一\n\
+        | ・ ・ ・ ・ ・ ・ ・v玉 ・|二\n\
+        | ・ ・ ・ ・ ・ ・v歩v歩 ・|三\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|四\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|五\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|六\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|七\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|八\n\
+        | ・ ・ ・ ・ ・ ・ ・ ・ ・|九\n\
+        +---------------------------+\n\
+        先手の持駒：金　銀二\n\
+        先手：a\n\
+        後手：b\n\
+        手数----指手---------消費時間--\n\
+        \x20  1 １二金打   ( 0:00/00:00:00)\n";
+    let kifu = read(txt).unwrap();
+    assert_eq!(kifu.sname(), Some(String::from("a")));
+    assert_eq!(kifu.start, "7nl/7k1/6pp1/9/9/9/9/9/9 b G2S 1");
+    assert_eq!(
+        sfen(kifu.position(0, None).unwrap()),
+        "7nl/7kG/6pp1/9/9/9/9/9/9 w 2S 2"
+    );
+    let txt = txt.replace("先手：a", "後手番").replace("１二金打", "投了");
+    let kifu = read(&txt).unwrap();
+    assert_eq!(kifu.start, "7nl/7k1/6pp1/9/9/9/9/9/9 w G2S 1");
+
+    let errs = [
+        ("手合割：九枚落ち", 1),
+        ("1 ７六歩(77)\n3 ３四歩(33)", 2),
+        ("1 ７六歩", 1),
+        ("1 同　歩(77)", 1),
+        ("1 ７六歩打(77)", 1),
+        ("1 ７六と", 1),
+        ("1 ７六歩(77)\n変化：2手", 2),
+        ("|v香v桂|一", 1),
+        ("手合割：その他\n|v香v桂v銀v金v玉v金v銀v桂v香|一", 2),
+        ("後手の持駒：玉", 1),
+        // the piece on the cell in () is different.
+        ("1 ７六飛(77)", 1),
+        ("1 ７七歩(76)", 1),
+        ("1 ７六と(77)", 1),
+        ("1 ７六歩(77)\n2 ３四と(33)", 2),
+    ];
+    for (txt, line) in errs {
+        match read(txt) {
+            Err(SfenError::Kifu { line: l, .. }) => assert_eq!(l, line, "{}", txt),
+            _ => panic!("{}", txt),
+        }
+    }
+    assert!(matches!(
+        read("1 ７五歩(77)"),
+        Err(SfenError::Move { ply: 1, .. })
+    ));
+}
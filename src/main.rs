@@ -1,18 +1,30 @@
 use axum::{
     extract::Query,
-    http::header::{HeaderMap, HeaderValue},
+    http::{
+        header::{HeaderMap, HeaderValue},
+        StatusCode,
+    },
     routing::get,
     Router,
 };
 use log::*;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use simplelog::*;
 use std::{fmt, fs::File, str::FromStr};
 
+mod bod;
+mod converter;
+mod csa;
+mod decoration;
+mod ki2;
+mod kif;
 mod myoptions;
+mod position;
 mod sfen;
+mod shellpool;
 mod svg2png;
 mod svgbuilder;
+mod theme;
 
 /// initiate log
 ///
@@ -46,15 +58,30 @@ fn initlog(logpath: &str) {
 /// global settings.
 static MYOPT: once_cell::sync::OnceCell<myoptions::MyOptions> = once_cell::sync::OnceCell::new();
 
+/// limits the number of png converters.
+static LIMITER: once_cell::sync::OnceCell<svg2png::Limiter> = once_cell::sync::OnceCell::new();
+
 #[tokio::main]
 async fn main() {
     //! entry point.
     MYOPT
         .set(myoptions::MyOptions::new(std::env::args().collect()))
         .unwrap();
+    let errors = &MYOPT.get().unwrap().errors;
+    if !errors.is_empty() {
+        for msg in errors {
+            eprintln!("{}", msg);
+        }
+        std::process::exit(1);
+    }
 
     initlog(&MYOPT.get().unwrap().logpath);
 
+    let myopt = MYOPT.get().unwrap();
+    LIMITER
+        .set(svg2png::Limiter::new(myopt.maxconverters, myopt.maxqueue))
+        .unwrap();
+
     info!("myoption:{:?}", MYOPT.get().unwrap());
     info!("CTRL + c to quit.");
 
@@ -79,13 +106,53 @@ fn app() -> Router {
 ///
 /// <h2>options</h2>
 ///
-/// - sfen<br>sfen text. this must be given.<br>
-///   ex. "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"
+/// - sfen<br>sfen text. this, usi, kif, ki2, csa or bod must be given.<br>
+///   ex. "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"<br>
+///   USI position command is also accepted.
+/// - usi<br>USI position command.<br>
+///   ex. "position startpos moves 7g7f 3c3d" or "position sfen (sfen) moves 7g7f"<br>
+///   the last move is highlighted unless lm is given.
+/// - kif<br>game record in KIF w/ header, moves and 変化.<br>
+///   sname, gname and title are taken from 先手, 後手, 棋戦 and 開始日時 unless given.
+/// - ki2<br>game record in KI2 w/ header, moves like ▲７六歩　△３四歩 and 変化.<br>
+///   moving pieces are found by 右, 左, 上, 引, 寄 and 直 if needed.
+/// - csa<br>game record in CSA w/ N+, N-, $EVENT, $START_TIME, PI or P1-P9, moves and results.<br>
+///   %TORYO, %SENNICHITE and so on are shown as turn=fb, fw or d after the last move unless turn is given.
+/// - bod<br>board diagram in BOD w/ names, hands, the board and 手数＝N　▲７六歩まで.<br>
+///   the last move is highlighted unless lm is given.
+/// - ply<br>number of moves to apply in usi, kif, ki2 or csa. default: all.
+/// - branch<br>branch in kif or ki2. 0 for the main line, 1 for the first 変化 and so on. default: 0.
 /// - sname<br>sente's name.
 /// - gname<br>gote's name.
 /// - title<br>title.
+/// - lm<br>last move. ex. 7776FU, +7776FU, 7g7f, P*5e or 76.<br>
+///   the piece, the promotion and the direction are worked out from sfen for +7776FU and 7g7f.
+/// - lmarrow<br>1 to draw an arrow for the last move.
+/// - caption<br>1 to put the last move like ▲７六歩まで after the title.
+/// - arrows<br>arrows in USI w/ optional colors. ex. 7g7f,2h2d:red,P*5e:#00f<br>
+///   drops start from the hand of the player to move.
+/// - marks<br>cells to paint w/ optional colors and opacity. ex. 55,44:#f88,33:#8f8@0.5
+/// - annotate<br>glyphs on cells. ex. 55:×,44:1@tl,33:①@c<br>
+///   ○×△▲□■◎●☆★!?→, 1 to 20 or ① to ⑳ at tl, tr, bl, br or c. default: tr.
 /// - turn<br>turn. b, w, fb, fw or d.
-/// - image<br>svg or png.
+/// - flip<br>1 to draw the board seen from gote. same as view=w.
+/// - view<br>b or w. the board is seen from gote for w.
+/// - image<br>svg, png, bod or txt. bod and txt are BOD text w/ sname, gname, title and lm.
+/// - theme<br>colors. light, dark, wood or print-mono. default: light or --theme.
+/// - fg<br>color of texts, lines and pieces. ex. red, #f00, rgb(255,0,0)
+/// - bg<br>background color.
+/// - transparent<br>1 for no background.
+/// - highlight<br>color of the cell the last move went to.
+/// - scale<br>scale of the image. ex. 2 for 520x550. default: 1.
+/// - width, height<br>size of the image in pixels. the aspect ratio is kept if only one is given.
+/// - validate<br>rule check. strict, warn or off. default: warn.<br>
+///   strict: 400 w/ JSON, warn: X-Sfen-Violation headers.
+///
+/// <h2>errors</h2>
+///
+/// invalid sfen, usi, kif, ki2, csa, bod, lm, arrows, marks or annotate is answered by 400 w/ JSON like<br>
+/// {"message":"...","field":"board","dan":3,"suji":5,"offset":20,"found":"x","expected":"..."}
+/// invalid ply, branch, theme, fg, bg, highlight, scale, width, height or validate is answered as "field":"param" w/ its name.
 ///
 /// <h2>example:</h2>
 /// http://localhost:7582/?sfen=lnsg3nl%2F1k3s1r1%2Fppppppgpp%2F6p2%2F7P1%2F2P2PP2%2FPPBPP1N1P%2F3K2SR1%2FLNSG1G2L+w+b+20&lm=37&sname=o-jill&gname=%E3%81%A2%E3%82%8B&title=2022%2F03%2F04+12%3A46%3A30&turn=d&image=svg
@@ -95,14 +162,52 @@ async fn help() -> axum::response::Html<&'static str> {
         "<html><head><title>help - sfenimageserver -</title></head>\
         <body><h1>sfenimageserver<h1>\
         <h2>options</h2>\
-        <ul><li>sfen<br>sfen text. this must be given.<br>\
-        ex. \"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1\"\
+        <ul><li>sfen<br>sfen text. this, usi, kif, ki2, csa or bod must be given.<br>\
+        ex. \"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1\"<br>\
+        USI position command is also accepted.\
+        <li>usi<br>USI position command.<br>\
+        ex. \"position startpos moves 7g7f 3c3d\" or \"position sfen (sfen) moves 7g7f\"<br>\
+        the last move is highlighted unless lm is given.\
+        <li>kif<br>game record in KIF w/ header, moves and 変化.<br>\
+        sname, gname and title are taken from 先手, 後手, 棋戦 and 開始日時 unless given.\
+        <li>ki2<br>game record in KI2 w/ header, moves like ▲７六歩　△３四歩 and 変化.<br>\
+        moving pieces are found by 右, 左, 上, 引, 寄 and 直 if needed.\
+        <li>csa<br>game record in CSA w/ N+, N-, $EVENT, $START_TIME, PI or P1-P9, moves and results.<br>\
+        %TORYO, %SENNICHITE and so on are shown as turn=fb, fw or d after the last move unless turn is given.\
+        <li>bod<br>board diagram in BOD w/ names, hands, the board and 手数＝N　▲７六歩まで.<br>\
+        the last move is highlighted unless lm is given.\
+        <li>ply<br>number of moves to apply in usi, kif, ki2 or csa. default: all.\
+        <li>branch<br>branch in kif or ki2. 0 for the main line, 1 for the first 変化 and so on. default: 0.\
         <li>sname<br>sente's name.\
         <li>gname<br>gote's name.\
         <li>title<br>title.\
+        <li>lm<br>last move. ex. 7776FU, +7776FU, 7g7f, P*5e or 76.<br>\
+        the piece, the promotion and the direction are worked out from sfen for +7776FU and 7g7f.\
+        <li>lmarrow<br>1 to draw an arrow for the last move.\
+        <li>caption<br>1 to put the last move like ▲７六歩まで after the title.\
+        <li>arrows<br>arrows in USI w/ optional colors. ex. 7g7f,2h2d:red,P*5e:#00f<br>\
+        drops start from the hand of the player to move.\
+        <li>marks<br>cells to paint w/ optional colors and opacity. ex. 55,44:#f88,33:#8f8@0.5\
+        <li>annotate<br>glyphs on cells. ex. 55:×,44:1@tl,33:①@c<br>\
+        ○×△▲□■◎●☆★!?→, 1 to 20 or ① to ⑳ at tl, tr, bl, br or c. default: tr.\
         <li>turn<br>turn. b, w, fb, fw or d.\
-        <li>image<br>svg or png.\
+        <li>flip<br>1 to draw the board seen from gote. same as view=w.\
+        <li>view<br>b or w. the board is seen from gote for w.\
+        <li>image<br>svg, png, bod or txt. bod and txt are BOD text w/ sname, gname, title and lm.\
+        <li>theme<br>colors. light, dark, wood or print-mono. default: light or --theme.\
+        <li>fg<br>color of texts, lines and pieces. ex. red, #f00, rgb(255,0,0)\
+        <li>bg<br>background color.\
+        <li>transparent<br>1 for no background.\
+        <li>highlight<br>color of the cell the last move went to.\
+        <li>scale<br>scale of the image. ex. 2 for 520x550. default: 1.\
+        <li>width, height<br>size of the image in pixels. the aspect ratio is kept if only one is given.\
+        <li>validate<br>rule check. strict, warn or off. default: warn.<br>\
+        strict: 400 w/ JSON, warn: X-Sfen-Violation headers.\
         </ul>\
+        <h2>errors</h2>\
+        invalid sfen, usi, kif, ki2, csa, bod, lm, arrows, marks or annotate is answered by 400 w/ JSON like<br>\
+        {\"message\":\"...\",\"field\":\"board\",\"dan\":3,\"suji\":5,\"offset\":20,\"found\":\"x\",\"expected\":\"...\"}<br>\
+        invalid ply, branch, theme, fg, bg, highlight, scale, width, height or validate is answered as \"field\":\"param\" w/ its name.\
         <h2>example:</h2>\
         http://localhost:7582/?sfen=lnsg3nl%2F1k3s1r1%2Fppppppgpp%2F6p2%2F7P1%2F2P2PP2%2FPPBPP1N1P%2F3K2SR1%2FLNSG1G2L+w+b+20&lm=37&sname=o-jill&gname=%E3%81%A2%E3%82%8B&title=2022%2F03%2F04+12%3A46%3A30&turn=d&image=svg
         </body></html>",
@@ -112,72 +217,342 @@ async fn help() -> axum::response::Html<&'static str> {
 /// HeaderValue for text/plain.
 static TEXTPLAIN: HeaderValue = HeaderValue::from_static("text/plain");
 
+/// HeaderValue for application/json.
+static APPJSON: HeaderValue = HeaderValue::from_static("application/json");
+
+/// body of the response for an invalid sfen or last move.
+#[derive(Serialize)]
+struct SfenErrorResponse<'a> {
+    /// human readable message.
+    message: String,
+    /// where and why it failed.
+    #[serde(flatten)]
+    error: &'a sfen::SfenError,
+}
+
+/// Returns 400 Bad Request w/ SfenError in JSON.
+///
+/// # Argument
+/// * `err` - error to be sent.
+fn sfenerror_response(err: sfen::SfenError) -> (StatusCode, HeaderMap, Vec<u8>) {
+    warn!("{}", err);
+    let body = SfenErrorResponse {
+        message: err.to_string(),
+        error: &err,
+    };
+    let mut h = HeaderMap::new();
+    h.insert(axum::http::header::CONTENT_TYPE, APPJSON.clone());
+    (
+        StatusCode::BAD_REQUEST,
+        h,
+        serde_json::to_vec(&body).unwrap_or_default(),
+    )
+}
+
+/// body of the response for a position against the rules.
+#[derive(Serialize)]
+struct ViolationResponse<'a> {
+    /// human readable message.
+    message: String,
+    /// rule violations.
+    violations: &'a [sfen::Violation],
+}
+
+/// Returns 400 Bad Request w/ rule violations in JSON.
+///
+/// # Argument
+/// * `violations` - violations to be sent.
+fn violation_response(violations: Vec<sfen::Violation>) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let msg = violations
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+    warn!("{}", msg);
+    let body = ViolationResponse {
+        message: msg,
+        violations: &violations,
+    };
+    let mut h = HeaderMap::new();
+    h.insert(axum::http::header::CONTENT_TYPE, APPJSON.clone());
+    (
+        StatusCode::BAD_REQUEST,
+        h,
+        serde_json::to_vec(&body).unwrap_or_default(),
+    )
+}
+
+/// add rule violations to the header as X-Sfen-Violation.
+///
+/// # Arguments
+/// * `h` - header to be added.
+/// * `violations` - violations to be added.
+fn add_violations(h: &mut HeaderMap, violations: &[sfen::Violation]) {
+    for v in violations {
+        if let Ok(val) = HeaderValue::from_str(&v.to_string()) {
+            h.append("x-sfen-violation", val);
+        }
+    }
+}
+
 /// process url.
 ///
 /// # Arguments
 /// * `params` - parameters from query string.
 ///
 /// # Return value
+/// * StatusCode - 400 w/ JSON for invalid sfen or last move,
+///   or for rule violations when `validate=strict`.
+///   500 or 504 for png converter errors.
+///   503 w/ Retry-After when too many requests are waiting for png converters.
 /// * Headermap - Header informations of the content.
 /// * Vec\<u8> - content to be sent.
-async fn handler(Query(params): Query<Params>) -> (HeaderMap, Vec<u8>) {
-    let result: String;
+async fn handler(Query(params): Query<Params>) -> (StatusCode, HeaderMap, Vec<u8>) {
     info!("call handler() : {:?}", params);
-    if params.sfen.is_none() {
-        let msg = "sfen is not specified...";
-        let mut h = HeaderMap::new();
-        h.insert(axum::http::header::CONTENT_TYPE, TEXTPLAIN.clone());
-        warn!("{}", msg);
-        return (h, msg.into());
+    let ply = match readparam("ply", &params.ply, "a number of moves") {
+        Ok(ply) => ply,
+        Err(err) => return sfenerror_response(err),
+    };
+    let branch = match readparam("branch", &params.branch, "an index of a branch") {
+        Ok(branch) => branch.unwrap_or(0),
+        Err(err) => return sfenerror_response(err),
+    };
+    let kifu = match (
+        params.kif.as_deref(),
+        params.ki2.as_deref(),
+        params.csa.as_deref(),
+    ) {
+        (Some(txt), _, _) => Some(kif::read(txt)),
+        (None, Some(txt), _) => Some(ki2::read(txt)),
+        (None, None, Some(txt)) => Some(csa::read(txt)),
+        (None, None, None) => None,
+    };
+    let kifu = match kifu {
+        Some(Ok(kifu)) => Some(kifu),
+        Some(Err(err)) => return sfenerror_response(err),
+        None => None,
+    };
+    let bod = match params.bod.as_deref().map(bod::read) {
+        Some(Ok(bod)) => Some(bod),
+        Some(Err(err)) => return sfenerror_response(err),
+        None => None,
+    };
+    let (sfen, usilm) = match params.usi.as_ref().or(params.sfen.as_ref()) {
+        _ if kifu.is_some() => {
+            let kifu = kifu.as_ref().unwrap();
+            match kifu.position(branch, ply) {
+                Ok(ret) => ret,
+                Err(err) => return sfenerror_response(err),
+            }
+        }
+        _ if bod.is_some() => {
+            let bod = bod.as_ref().unwrap();
+            (bod.sfen.clone(), bod.lastmove.clone())
+        }
+        None => {
+            let msg = "sfen is not specified...";
+            let mut h = HeaderMap::new();
+            h.insert(axum::http::header::CONTENT_TYPE, TEXTPLAIN.clone());
+            warn!("{}", msg);
+            return (StatusCode::OK, h, msg.into());
+        }
+        Some(txt) if params.usi.is_some() || position::is_usi(txt) => {
+            match position::read_usi(txt, ply) {
+                Ok(ret) => ret,
+                Err(err) => return sfenerror_response(err),
+            }
+        }
+        Some(txt) => match sfen::Sfen::new(txt) {
+            Ok(sfen) => (sfen, None),
+            Err(err) => return sfenerror_response(err),
+        },
+    };
+    let validation = match params.validate.as_deref() {
+        None => sfen::Validation::Warn,
+        Some(txt) => match sfen::Validation::from(txt) {
+            Some(v) => v,
+            None => {
+                return sfenerror_response(sfen::SfenError::Param {
+                    name: "validate",
+                    found: txt.to_string(),
+                    expected: "strict, warn or off",
+                })
+            }
+        },
+    };
+    let violations = if validation == sfen::Validation::Off {
+        Vec::new()
     } else {
-        let sfen = sfen::Sfen::new(&params.sfen.unwrap());
-        let lm = if params.lm.is_some() {
-            match sfen::LastMove::read(&params.lm.unwrap()) {
+        match sfen.validate() {
+            Ok(v) => v,
+            Err(err) => return sfenerror_response(err),
+        }
+    };
+    if validation == sfen::Validation::Strict && !violations.is_empty() {
+        return violation_response(violations);
+    }
+    let lm = match &params.lm {
+        Some(txt) => match position::Move::from_usi(txt, 1) {
+            Ok(mv) => match position::lastmove_after(&sfen, &mv) {
                 Ok(ret) => ret,
-                Err(msg) => {
-                    warn!("{}", msg);
-                    sfen::LastMove::new()
+                Err(err) => return sfenerror_response(err),
+            },
+            Err(_) => {
+                match csa::lastmove_after(&sfen, txt).unwrap_or_else(|| sfen::LastMove::read(txt)) {
+                    Ok(ret) => ret,
+                    Err(err) => return sfenerror_response(err),
                 }
             }
-        } else {
-            sfen::LastMove::new()
-        };
-        match sfen.to_svg(
-            lm.topos(),
-            params.turn,
-            params.sname,
-            params.gname,
-            params.title,
+        },
+        None => usilm.unwrap_or_else(sfen::LastMove::new),
+    };
+    let (turn, sname, gname, title) = match (&kifu, bod) {
+        (Some(kifu), _) => (
+            params.turn.or_else(|| kifu.turn(branch, ply)),
+            params.sname.or_else(|| kifu.sname()),
+            params.gname.or_else(|| kifu.gname()),
+            params.title.or_else(|| kifu.title()),
+        ),
+        (None, Some(bod)) => (
+            params.turn.or(bod.result),
+            params.sname.or(bod.sname),
+            params.gname.or(bod.gname),
+            params.title.or(bod.title),
+        ),
+        (None, None) => (params.turn, params.sname, params.gname, params.title),
+    };
+    let image = params.image.unwrap_or_else(|| String::from("svg"));
+    if ["bod", ".bod", "txt", ".txt"].contains(&image.as_str()) {
+        let txt = match sfen.dump(
+            sname.as_deref().unwrap_or(""),
+            gname.as_deref().unwrap_or(""),
+            title.as_deref().unwrap_or(""),
+            lm,
+            turn.as_deref(),
         ) {
-            Ok(svg) => result = svg.to_string(),
-            Err(msg) => {
-                warn!("{}", msg);
-                let mut h = HeaderMap::new();
-                h.insert(axum::http::header::CONTENT_TYPE, TEXTPLAIN.clone());
-                return (h, msg.into_bytes());
+            Ok(txt) => txt,
+            Err(err) => return sfenerror_response(err),
+        };
+        let mut h = HeaderMap::new();
+        h.insert(
+            axum::http::header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        add_violations(&mut h, &violations);
+        return (StatusCode::OK, h, txt.into_bytes());
+    }
+    let mut theme = match params.theme.as_deref() {
+        None => MYOPT.get().unwrap().theme.clone(),
+        Some(txt) => match theme::Theme::from(txt) {
+            Some(theme) => theme,
+            None => {
+                return sfenerror_response(sfen::SfenError::Param {
+                    name: "theme",
+                    found: txt.to_string(),
+                    expected: "light, dark, wood or print-mono",
+                })
+            }
+        },
+    };
+    let colors = [
+        ("fg", &params.fg),
+        ("bg", &params.bg),
+        ("highlight", &params.highlight),
+    ];
+    for (nm, color) in colors {
+        match color.as_deref() {
+            Some(txt) if !theme::is_color(txt) => {
+                return sfenerror_response(sfen::SfenError::Param {
+                    name: nm,
+                    found: txt.to_string(),
+                    expected: "a css color like red, #f00 or rgb(255,0,0)",
+                })
             }
+            Some(txt) if nm == "fg" => theme.set_fg(txt),
+            Some(txt) if nm == "bg" => theme.bg = txt.to_string(),
+            Some(txt) => theme.lastmove = txt.to_string(),
+            None => {}
         }
     }
-    let result = result.replace("black", &MYOPT.get().unwrap().fgcolor);
-    let image = params.image.unwrap_or(String::from("svg"));
+    let arrows = match params
+        .arrows
+        .as_deref()
+        .map(|txt| decoration::read_arrows(txt, &theme.arrow))
+    {
+        Some(Ok(arrows)) => arrows,
+        Some(Err(err)) => return sfenerror_response(err),
+        None => Vec::new(),
+    };
+    let marks = match params
+        .marks
+        .as_deref()
+        .map(|txt| decoration::read_marks(txt, &theme.mark))
+    {
+        Some(Ok(marks)) => marks,
+        Some(Err(err)) => return sfenerror_response(err),
+        None => Vec::new(),
+    };
+    let annotations = match params.annotate.as_deref().map(decoration::read_annotations) {
+        Some(Ok(annotations)) => annotations,
+        Some(Err(err)) => return sfenerror_response(err),
+        None => Vec::new(),
+    };
+    let deco = decoration::Decoration {
+        flip: params.flip.as_deref() == Some("1") || params.view.as_deref() == Some("w"),
+        transparent: params.transparent.as_deref() == Some("1"),
+        lmarrow: params.lmarrow.as_deref() == Some("1"),
+        caption: params.caption.as_deref() == Some("1"),
+        arrows,
+        marks,
+        annotations,
+        theme,
+    };
+    let size = readparam("scale", &params.scale, "a positive number").and_then(|scale| {
+        let width = readparam("width", &params.width, "a number of pixels")?;
+        let height = readparam("height", &params.height, "a number of pixels")?;
+        svgbuilder::imagesize(scale, width, height)
+    });
+    let size = match size {
+        Ok(size) => size,
+        Err(err) => return sfenerror_response(err),
+    };
+    let result = match sfen.to_svg(&lm, &deco, turn, sname, gname, title) {
+        Ok(mut svg) => {
+            svg.resize(size.0, size.1);
+            svg.to_string()
+        }
+        Err(err) => return sfenerror_response(err),
+    };
     if image == "png" || image == ".png" {
         let mut opt = svg2png::Svg2PngConfig::new();
-        opt.typ = MYOPT.get().unwrap().svg2png;
-        opt.bgcolor = MYOPT.get().unwrap().bgcolor.clone();
-        match svg2png::start(result.to_string(), opt) {
+        opt.typ = MYOPT.get().unwrap().svg2png.clone();
+        opt.fontdir = MYOPT.get().unwrap().fontdir.clone();
+        opt.timeout = MYOPT.get().unwrap().timeout;
+        opt.size = Some(size);
+        match LIMITER.get().unwrap().start(result, opt).await {
             Ok(png) => {
                 let mut h = HeaderMap::new();
                 h.insert(
                     axum::http::header::CONTENT_TYPE,
                     HeaderValue::from_static("image/png"),
                 );
-                (h, png)
+                add_violations(&mut h, &violations);
+                (StatusCode::OK, h, png)
             }
-            Err(msg) => {
-                warn!("{}", msg);
+            Err(err) => {
+                error!("{}", err);
                 let mut h = HeaderMap::new();
                 h.insert(axum::http::header::CONTENT_TYPE, TEXTPLAIN.clone());
-                (h, msg.into_bytes())
+                let status = match err {
+                    svg2png::Svg2PngError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+                    svg2png::Svg2PngError::Busy { .. } => {
+                        let sec = MYOPT.get().unwrap().timeout.as_secs().max(1);
+                        h.insert(axum::http::header::RETRY_AFTER, HeaderValue::from(sec));
+                        StatusCode::SERVICE_UNAVAILABLE
+                    }
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                (status, h, err.to_string().into_bytes())
             }
         }
     } else if image == "svg" || image == ".svg" {
@@ -186,13 +561,14 @@ async fn handler(Query(params): Query<Params>) -> (HeaderMap, Vec<u8>) {
             axum::http::header::CONTENT_TYPE,
             HeaderValue::from_static("image/svg+xml"),
         );
-        (h, result.clone().into_bytes())
+        add_violations(&mut h, &violations);
+        (StatusCode::OK, h, result.into_bytes())
     } else {
         let msg = format!("invalid image type. \"{}\"", image);
         let mut h = HeaderMap::new();
         h.insert(axum::http::header::CONTENT_TYPE, TEXTPLAIN.clone());
         warn!("{}", msg);
-        return (h, msg.into());
+        (StatusCode::OK, h, msg.into())
     }
 }
 
@@ -201,19 +577,106 @@ async fn handler(Query(params): Query<Params>) -> (HeaderMap, Vec<u8>) {
 struct Params {
     #[serde(default, deserialize_with = "empty_string_as_none")]
     /// sfen text. ex. lnsgkgsns/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1
+    /// USI position command is also accepted.
     sfen: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// USI position command. ex. position startpos moves 7g7f 3c3d
+    usi: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// game record in KIF. sname, gname and title are taken from the header.
+    kif: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// game record in KI2. sname, gname and title are taken from the header.
+    ki2: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// game record in CSA. the result is shown as the turn marker at the end.
+    csa: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// board diagram in BOD. names, title and the last move are taken from it.
+    bod: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// number of moves to apply in usi, kif, ki2 or csa. all moves by default.
+    ply: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// index of the branch in kif or ki2. 0 for the main line, 1 for the first 変化.
+    branch: Option<String>,
     /// sente's name.
     sname: Option<String>,
     /// gote's name.
     gname: Option<String>,
     /// title.
     title: Option<String>,
-    /// last move. ex.7776FU or simply 76
+    /// last move. ex.7776FU, simply 76, +7776FU in CSA or 7g7f in USI
     lm: Option<String>,
+    /// draw an arrow for the last move if 1.
+    lmarrow: Option<String>,
+    /// put the last move after the title if 1.
+    caption: Option<String>,
+    /// arrows. ex. 7g7f,2h2d:red,P*5e:blue
+    arrows: Option<String>,
+    /// painted cells. ex. 55,44:#f88,33:#8f8@0.5
+    marks: Option<String>,
+    /// glyphs on cells. ex. 55:×,44:1@tl,33:①@c
+    annotate: Option<String>,
     /// which turn it is now. b or w.
     turn: Option<String>,
-    /// image type. png or svg
+    /// draw the board seen from gote if 1.
+    flip: Option<String>,
+    /// b or w. draw the board seen from gote for w.
+    view: Option<String>,
+    /// image type. png, svg, bod or txt
     image: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// colors. light, dark, wood or print-mono.
+    theme: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// color of texts, lines and pieces.
+    fg: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// background color.
+    bg: Option<String>,
+    /// no background if 1.
+    transparent: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// color of the cell the last move went to.
+    highlight: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// scale of the image. 1 by default.
+    scale: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// width of the image in pixels.
+    width: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    /// height of the image in pixels.
+    height: Option<String>,
+    /// rule check. strict, warn or off. warn by default.
+    validate: Option<String>,
+}
+
+/// Returns a number in the query string.
+///
+/// # Arguments
+/// * `name` - name of the parameter.
+/// * `val` - value of the parameter.
+/// * `expected` - what is expected for errors.
+/// # Return value
+/// the number, None if not specified or SfenError::Param.
+fn readparam<T: FromStr>(
+    name: &'static str,
+    val: &Option<String>,
+    expected: &'static str,
+) -> Result<Option<T>, sfen::SfenError> {
+    match val.as_deref() {
+        None => Ok(None),
+        Some(txt) => match txt.parse() {
+            Ok(num) => Ok(Some(num)),
+            Err(_) => Err(sfen::SfenError::Param {
+                name,
+                found: txt.to_string(),
+                expected,
+            }),
+        },
+    }
 }
 
 /// deserialize querystring to struct Params.
@@ -10,10 +10,20 @@ pub struct MyOptions {
     pub logpath: String,
     /// svg2png type.
     pub svg2png: svg2png::Type,
-    /// background color.
-    pub bgcolor: String,
-    /// foreground color.
-    pub fgcolor: String,
+    /// converters defined as command templates including built-ins.
+    pub converters: Vec<converter::Template>,
+    /// directory of fonts for svg2png::Type::NATIVE.
+    pub fontdir: String,
+    /// time limit for png converters.
+    pub timeout: std::time::Duration,
+    /// max number of png converters running at once.
+    pub maxconverters: usize,
+    /// max number of requests waiting for png converters.
+    pub maxqueue: usize,
+    /// colors.
+    pub theme: theme::Theme,
+    /// invalid args.
+    pub errors: Vec<String>,
 }
 
 impl MyOptions {
@@ -27,40 +37,57 @@ impl MyOptions {
     /// default:<br>
     /// - port: "7582"
     /// - logpath: ""
-    /// - svg2png: svg2png::Type::RSVG
-    /// - bgcolor: "white"
-    /// - fgcolor: "black"
+    /// - svg2png: rsvg
+    /// - converters: built-ins
+    /// - fontdir: "fonts"
+    /// - timeout: 10 sec
+    /// - maxconverters: 4
+    /// - maxqueue: 16
+    /// - theme: light. fgcolor: "black", bgcolor: "white"
+    /// - errors: empty
     pub fn new(args: Vec<String>) -> MyOptions {
+        let converters = converter::builtins();
         let mut opt = MyOptions {
             port: String::from("7582"),
             logpath: String::new(),
-            svg2png: svg2png::Type::RSVG,
-            bgcolor: String::from("white"),
-            fgcolor: String::from("black"),
+            svg2png: svg2png::Type::from("rsvg", &converters).unwrap(),
+            converters,
+            fontdir: String::from("fonts"),
+            timeout: std::time::Duration::from_secs(10),
+            maxconverters: 4,
+            maxqueue: 16,
+            theme: theme::Theme::light(),
+            errors: Vec::new(),
         };
         let mut old = String::new();
+        let mut name = String::from("rsvg");
         for e in args {
-            if e == "--port" {
-                old = e;
-            } else if e == "--log" {
+            if e == "--port"
+                || e == "--log"
+                || e == "--bgcolor"
+                || e == "--fgcolor"
+                || e == "--theme"
+                || e == "--fontdir"
+                || e == "--timeout"
+                || e == "--max-converters"
+                || e == "--max-queue"
+                || e == "--converter"
+                || e == "--define-converter"
+                || e == "--converter-file"
+            {
                 old = e;
             } else if e == "--dark" {
-                opt.bgcolor = String::from("black");
-                opt.fgcolor = String::from("white");
+                opt.theme = theme::Theme::dark();
                 old = String::new();
             } else if e == "--light" {
-                opt.bgcolor = String::from("white");
-                opt.fgcolor = String::from("black");
-                old = String::new();
-            } else if e == "--bgcolor" {
-                old = e;
-            } else if e == "--fgcolor" {
-                old = e;
-            } else if e == "--rsvg" {
-                opt.svg2png = svg2png::Type::RSVG;
+                opt.theme = theme::Theme::light();
                 old = String::new();
-            } else if e == "--inkscape11" {
-                opt.svg2png = svg2png::Type::INKSCAPE11;
+            } else if e == "--rsvg"
+                || e == "--inkscape11"
+                || e == "--inkscape-shell"
+                || e == "--native"
+            {
+                name = e[2..].to_string();
                 old = String::new();
             } else {
                 if old == "--port" {
@@ -69,14 +96,132 @@ impl MyOptions {
                     }
                 } else if old == "--log" {
                     opt.logpath = e;
+                } else if old == "--fontdir" {
+                    opt.fontdir = e;
+                } else if old == "--max-converters" {
+                    if let Ok(num) = e.parse::<usize>() {
+                        opt.maxconverters = num.max(1);
+                    }
+                } else if old == "--max-queue" {
+                    if let Ok(num) = e.parse::<usize>() {
+                        opt.maxqueue = num;
+                    }
+                } else if old == "--timeout" {
+                    if let Ok(sec) = e.parse::<f64>() {
+                        if sec > 0.0 && sec.is_finite() {
+                            opt.timeout = std::time::Duration::from_secs_f64(sec);
+                        }
+                    }
                 } else if old == "--bgcolor" {
-                    opt.bgcolor = e;
+                    if theme::is_color(&e) {
+                        opt.theme.bg = e;
+                    }
                 } else if old == "--fgcolor" {
-                    opt.fgcolor = e;
+                    if theme::is_color(&e) {
+                        opt.theme.set_fg(&e);
+                    }
+                } else if old == "--theme" {
+                    match theme::Theme::from(&e) {
+                        Some(theme) => opt.theme = theme,
+                        None => opt
+                            .errors
+                            .push(format!("--theme: unknown theme \"{}\".", e)),
+                    }
+                } else if old == "--converter" {
+                    name = e;
+                } else if old == "--define-converter" {
+                    match converter::Template::define(&e) {
+                        Ok(tmpl) => opt.define(tmpl),
+                        Err(msg) => opt.errors.push(format!("--define-converter: {}", msg)),
+                    }
+                } else if old == "--converter-file" {
+                    match std::fs::read_to_string(&e)
+                        .map_err(|msg| msg.to_string())
+                        .and_then(|txt| converter::read_definitions(&txt))
+                    {
+                        Ok(defs) => {
+                            for tmpl in defs {
+                                opt.define(tmpl);
+                            }
+                        }
+                        Err(msg) => opt.errors.push(format!("--converter-file {}: {}", e, msg)),
+                    }
                 }
                 old = String::new();
             }
         }
+        match svg2png::Type::from(&name, &opt.converters) {
+            Some(typ) => opt.svg2png = typ,
+            None => opt
+                .errors
+                .push(format!("--converter: unknown converter \"{}\".", name)),
+        }
         opt
     }
+
+    /// add a converter or replace the one w/ the same name.
+    ///
+    /// # Argument
+    /// * `tmpl` - converter.
+    fn define(&mut self, tmpl: converter::Template) {
+        match self.converters.iter_mut().find(|t| t.name == tmpl.name) {
+            Some(t) => *t = tmpl,
+            None => self.converters.push(tmpl),
+        }
+    }
+}
+
+#[test]
+fn convertertest() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let defs = converter::builtins();
+    let opt = MyOptions::new(args(&[]));
+    assert_eq!(opt.svg2png, svg2png::Type::from("rsvg", &defs).unwrap());
+    let opt = MyOptions::new(args(&["--inkscape11"]));
+    assert_eq!(
+        opt.svg2png,
+        svg2png::Type::from("inkscape11", &defs).unwrap()
+    );
+    let opt = MyOptions::new(args(&["--native"]));
+    assert_eq!(opt.svg2png, svg2png::Type::NATIVE);
+    let opt = MyOptions::new(args(&["--converter", "imagemagick"]));
+    assert_eq!(
+        opt.svg2png,
+        svg2png::Type::from("imagemagick", &defs).unwrap()
+    );
+    assert!(opt.errors.is_empty());
+    let opt = MyOptions::new(args(&["--converter", "unknown"]));
+    assert_eq!(opt.svg2png, svg2png::Type::from("rsvg", &defs).unwrap());
+    assert_eq!(opt.errors.len(), 1);
+    let opt = MyOptions::new(args(&["--define-converter", "mine"]));
+    assert_eq!(opt.errors.len(), 1);
+    let opt = MyOptions::new(args(&["--converter-file", "no such file"]));
+    assert_eq!(opt.errors.len(), 1);
+
+    // defined after selected.
+    let opt = MyOptions::new(args(&[
+        "--converter",
+        "mine",
+        "--define-converter",
+        "mine=my-conv [-b {bg}] -",
+    ]));
+    let mine = converter::Template::define("mine=my-conv [-b {bg}] -").unwrap();
+    assert_eq!(opt.svg2png, svg2png::Type::COMMAND(mine));
+    assert_eq!(opt.converters.len(), defs.len() + 1);
+    // built-in is replaced.
+    let opt = MyOptions::new(args(&["--define-converter", "rsvg=rsvg-convert2"]));
+    let rsvg = converter::Template::define("rsvg=rsvg-convert2").unwrap();
+    assert_eq!(opt.svg2png, svg2png::Type::COMMAND(rsvg));
+    assert_eq!(opt.converters.len(), defs.len());
+}
+
+#[test]
+fn themeoptiontest() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let opt = MyOptions::new(args(&["--theme", "wood"]));
+    assert_eq!(opt.theme, theme::Theme::wood());
+    assert!(opt.errors.is_empty());
+    let opt = MyOptions::new(args(&["--theme", "bogus"]));
+    assert_eq!(opt.theme, theme::Theme::light());
+    assert_eq!(opt.errors.len(), 1);
 }
@@ -0,0 +1,969 @@
+use super::*;
+use sfen::*;
+
+/// sfen of the initial position.
+pub const STARTPOS: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+/// pieces which can be in hands. same order as `Position::sentegoma`.
+const TEGOMA: [KomaType; 7] = [
+    KomaType::Fu,
+    KomaType::Kyosha,
+    KomaType::Keima,
+    KomaType::Gin,
+    KomaType::Kin,
+    KomaType::Kaku,
+    KomaType::Hisha,
+];
+
+/// a move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Move {
+    /// cell index before move. None for a drop.
+    pub from: Option<(usize, usize)>,
+    /// cell index after move.
+    pub to: (usize, usize),
+    /// type of the dropped piece. `Aki` when the piece moves on the board.
+    pub drop: KomaType,
+    /// promote or not.
+    pub promote: bool,
+}
+
+/// read a cell in USI format like "7g".
+///
+/// # Argument
+/// * `txt` - 2 letters, suji in 1-9 and dan in a-i.
+fn usi2pos(txt: &[char]) -> Option<(usize, usize)> {
+    let suji = txt.first()?.to_digit(10)? as usize;
+    let ch = *txt.get(1)?;
+    let dan = "abcdefghi".chars().position(|c| c == ch)? + 1;
+    if suji == 0 {
+        return None;
+    }
+    Some((suji, dan))
+}
+
+impl Move {
+    /// Returns Move from USI expression like "7g7f", "8h2b+" or "P*5e".
+    ///
+    /// # Arguments
+    /// * `txt` - move in USI format.
+    /// * `ply` - index in the move list. used for the error.
+    /// # Return value
+    /// Move or SfenError.
+    pub fn from_usi(txt: &str, ply: usize) -> Result<Move, SfenError> {
+        let err = SfenError::Move {
+            ply,
+            found: txt.to_string(),
+            expected: "USI move like 7g7f, 8h2b+ or P*5e",
+        };
+        let chars: Vec<char> = txt.chars().collect();
+        if chars.len() == 4 && chars[1] == '*' {
+            let drop = KomaType::from(chars[0]);
+            if !chars[0].is_ascii_uppercase() || !TEGOMA.contains(&drop) {
+                return Err(err);
+            }
+            return match usi2pos(&chars[2..]) {
+                Some(to) => Ok(Move {
+                    from: None,
+                    to,
+                    drop,
+                    promote: false,
+                }),
+                None => Err(err),
+            };
+        }
+        if chars.len() != 4 && !(chars.len() == 5 && chars[4] == '+') {
+            return Err(err);
+        }
+        match (usi2pos(&chars[0..2]), usi2pos(&chars[2..4])) {
+            (Some(from), Some(to)) => Ok(Move {
+                from: Some(from),
+                to,
+                drop: KomaType::Aki,
+                promote: chars.len() == 5,
+            }),
+            _ => Err(err),
+        }
+    }
+
+    /// Returns USI expression like "7g7f".
+    pub fn to_usi(&self) -> String {
+        let pos = |(suji, dan): (usize, usize)| {
+            format!("{}{}", suji, "abcdefghi".chars().nth(dan - 1).unwrap())
+        };
+        match self.from {
+            Some(from) => format!(
+                "{}{}{}",
+                pos(from),
+                pos(self.to),
+                if self.promote { "+" } else { "" }
+            ),
+            None => format!("{}*{}", self.drop.to_sfen(), pos(self.to)),
+        }
+    }
+}
+
+#[test]
+fn movetest() {
+    let mv = Move::from_usi("7g7f", 1).unwrap();
+    assert_eq!(mv.from, Some((7, 7)));
+    assert_eq!(mv.to, (7, 6));
+    assert_eq!(mv.drop, KomaType::Aki);
+    assert!(!mv.promote);
+    assert_eq!(mv.to_usi(), "7g7f");
+    let mv = Move::from_usi("8h2b+", 1).unwrap();
+    assert_eq!(mv.from, Some((8, 8)));
+    assert_eq!(mv.to, (2, 2));
+    assert!(mv.promote);
+    assert_eq!(mv.to_usi(), "8h2b+");
+    let mv = Move::from_usi("P*5e", 1).unwrap();
+    assert_eq!(mv.from, None);
+    assert_eq!(mv.to, (5, 5));
+    assert_eq!(mv.drop, KomaType::Fu);
+    assert_eq!(mv.to_usi(), "P*5e");
+    for txt in ["", "7g7", "7g7j", "0a1a", "7g7f=", "K*5e", "p*5e", "P*5e+"] {
+        assert!(matches!(
+            Move::from_usi(txt, 3),
+            Err(SfenError::Move { ply: 3, .. })
+        ));
+    }
+}
+
+/// board and hands to apply moves.
+#[derive(Clone, Debug)]
+pub struct Position {
+    /// pieces on board. ban[dan - 1][9 - suji].
+    ban: Vec<Vec<Koma>>,
+    /// number of pieces in sente's hand. in order of `TEGOMA`.
+    sentegoma: [usize; 7],
+    /// number of pieces in gote's hand. in order of `TEGOMA`.
+    gotegoma: [usize; 7],
+    /// which turn it is.
+    teban: Teban,
+    /// number of nth move.
+    nteme: i32,
+}
+
+impl Position {
+    /// Returns Position or SfenError.
+    ///
+    /// # Argument
+    /// * `sfen` - position to start from.
+    pub fn from_sfen(sfen: &Sfen) -> Result<Position, SfenError> {
+        let ban = sfen.extractban()?;
+        if ban.len() != 9 {
+            return Err(SfenError::Board {
+                dan: ban.len(),
+                suji: 0,
+                offset: 0,
+                found: format!("{} rows", ban.len()),
+                expected: "9 rows",
+            });
+        }
+        if let Some((i, dan)) = ban.iter().enumerate().find(|(_, dan)| dan.len() != 9) {
+            return Err(SfenError::Board {
+                dan: i + 1,
+                suji: 0,
+                offset: 0,
+                found: format!("{} cells", dan.len()),
+                expected: "9 cells",
+            });
+        }
+        let teban = sfen.teban();
+        if teban == Teban::None {
+            return Err(SfenError::Turn {
+                found: String::new(),
+                expected: "b or w",
+            });
+        }
+        let (stgm, gtgm) = sfen.extracttegoma()?;
+        let mut sentegoma = [0; 7];
+        let mut gotegoma = [0; 7];
+        for (tegoma, tgm) in [(&mut sentegoma, stgm), (&mut gotegoma, gtgm)] {
+            for t in tgm {
+                if let Some(idx) = TEGOMA.iter().position(|&k| k == t.komatype()) {
+                    tegoma[idx] += t.num();
+                }
+            }
+        }
+        Ok(Position {
+            ban,
+            sentegoma,
+            gotegoma,
+            teban,
+            nteme: sfen.nteme(),
+        })
+    }
+
+    /// Returns a position w/o any pieces. sente moves first.
+    pub fn empty() -> Position {
+        Position {
+            ban: vec![vec![Koma::from(' ', Promotion::None); 9]; 9],
+            sentegoma: [0; 7],
+            gotegoma: [0; 7],
+            teban: Teban::Sente,
+            nteme: 1,
+        }
+    }
+
+    /// put a piece on a cell.
+    ///
+    /// # Arguments
+    /// * `(suji, dan)` - cell index. 1 to 9.
+    /// * `koma` - piece. blank to remove.
+    pub fn put(&mut self, (suji, dan): (usize, usize), koma: Koma) {
+        self.ban[dan - 1][9 - suji] = koma;
+    }
+
+    /// set number of pieces of a type in a player's hand.
+    ///
+    /// # Arguments
+    /// * `teban` - player.
+    /// * `koma` - type of the piece. fu to hisha.
+    /// * `num` - number of pieces.
+    pub fn set_tegoma(&mut self, teban: Teban, koma: KomaType, num: usize) {
+        if let Some(idx) = TEGOMA.iter().position(|&k| k == koma) {
+            if teban == Teban::Sente {
+                self.sentegoma[idx] = num;
+            } else if teban == Teban::Gote {
+                self.gotegoma[idx] = num;
+            }
+        }
+    }
+
+    /// set which turn it is.
+    ///
+    /// # Argument
+    /// * `teban` - player to move.
+    pub fn set_teban(&mut self, teban: Teban) {
+        self.teban = teban;
+    }
+
+    /// Returns the piece at a cell.
+    ///
+    /// # Argument
+    /// * `(suji, dan)` - cell index. 1 to 9.
+    pub fn koma(&self, (suji, dan): (usize, usize)) -> &Koma {
+        &self.ban[dan - 1][9 - suji]
+    }
+
+    /// Returns which turn it is.
+    pub fn teban(&self) -> Teban {
+        self.teban
+    }
+
+    /// Returns number of pieces of a type in a player's hand.
+    ///
+    /// # Arguments
+    /// * `teban` - player.
+    /// * `koma` - type of the piece.
+    pub fn tegoma(&self, teban: Teban, koma: KomaType) -> usize {
+        match TEGOMA.iter().position(|&k| k == koma) {
+            Some(idx) if teban == Teban::Sente => self.sentegoma[idx],
+            Some(idx) if teban == Teban::Gote => self.gotegoma[idx],
+            _ => 0,
+        }
+    }
+
+    /// Returns LastMove for a move to be applied to this position.
+    /// the piece, promotion and the direction to tell pieces apart are filled.
+    ///
+    /// # Argument
+    /// * `mv` - move to be applied.
+    pub fn lastmove(&self, mv: &Move) -> LastMove {
+        let mut lm = LastMove::new();
+        lm.to = mv.to;
+        lm.teban = self.teban;
+        match mv.from {
+            Some(from) => {
+                let k = self.koma(from).clone();
+                let zone = |dan: usize| {
+                    if k.is_sente() {
+                        dan <= 3
+                    } else {
+                        dan >= 7
+                    }
+                };
+                lm.from = from;
+                lm.teban = k.teban();
+                lm.promote = if mv.promote {
+                    Promotion::Promoted
+                } else if k.komatype().is_promotable()
+                    && !k.promotion().is_promoted()
+                    && (zone(from.1) || zone(mv.to.1))
+                {
+                    Promotion::NotPromoted
+                } else {
+                    Promotion::None
+                };
+                lm.dir = self.direction(from, mv.to);
+                lm.koma = k;
+            }
+            None => lm.koma = Koma::new(mv.drop, Promotion::None, self.teban),
+        }
+        lm
+    }
+
+    /// Returns the shortest direction letters to tell the moving piece
+    /// from the other same pieces which can move to the same cell.
+    /// see `LastMove::dir` for the letters.
+    ///
+    /// # Arguments
+    /// * `from` - cell index of the moving piece.
+    /// * `to` - cell index to go.
+    fn direction(&self, from: (usize, usize), to: (usize, usize)) -> String {
+        let k = self.koma(from);
+        let others: Vec<(usize, usize)> = (1..=9)
+            .flat_map(|suji| (1..=9).map(move |dan| (suji, dan)))
+            .filter(|&c| {
+                let o = self.koma(c);
+                c != from
+                    && o.teban() == k.teban()
+                    && o.komatype() == k.komatype()
+                    && o.promotion().is_promoted() == k.promotion().is_promoted()
+                    && self.can_reach(c, to)
+            })
+            .collect();
+        if others.is_empty() {
+            return String::new();
+        }
+        // cell index from the player's view. smaller is right or upper.
+        let rel = |(suji, dan): (usize, usize)| {
+            if k.is_sente() {
+                (suji as i32, dan as i32)
+            } else {
+                (10 - suji as i32, 10 - dan as i32)
+            }
+        };
+        let motion = |c: (usize, usize)| match rel(to).1 - rel(c).1 {
+            dy if dy < 0 => 'U',
+            dy if dy > 0 => 'H',
+            _ => 'Y',
+        };
+        let m = motion(from);
+        if others.iter().all(|&c| motion(c) != m) {
+            return m.to_string();
+        }
+        let (fx, fy) = rel(from);
+        let (tx, ty) = rel(to);
+        if uses_choku(k.komatype()) && fx == tx && fy == ty + 1 {
+            return String::from("C");
+        }
+        let side = |cells: &[(usize, usize)]| {
+            if cells.iter().all(|&c| rel(c).0 > fx) {
+                Some('R')
+            } else if cells.iter().all(|&c| rel(c).0 < fx) {
+                Some('L')
+            } else {
+                None
+            }
+        };
+        if let Some(h) = side(&others) {
+            return h.to_string();
+        }
+        let same: Vec<(usize, usize)> = others.into_iter().filter(|&c| motion(c) == m).collect();
+        match side(&same) {
+            Some(h) => format!("{}{}", h, m),
+            None => m.to_string(),
+        }
+    }
+
+    /// Returns the cells of the pieces which can move to a cell and fit the direction letters.
+    /// this is the reverse of `direction`.
+    ///
+    /// # Arguments
+    /// * `to` - cell index to go.
+    /// * `koma` - type of the piece of the player to move.
+    /// * `promoted` - the piece is promoted or not before the move.
+    /// * `dir` - direction letters. see `LastMove::dir`.
+    pub fn origins(
+        &self,
+        to: (usize, usize),
+        koma: KomaType,
+        promoted: bool,
+        dir: &str,
+    ) -> Vec<(usize, usize)> {
+        let mut cells: Vec<(usize, usize)> = (1..=9)
+            .flat_map(|suji| (1..=9).map(move |dan| (suji, dan)))
+            .filter(|&c| {
+                let k = self.koma(c);
+                k.teban() == self.teban
+                    && k.komatype() == koma
+                    && k.promotion().is_promoted() == promoted
+                    && self.can_reach(c, to)
+            })
+            .collect();
+        // cell index from the player's view. smaller is right or upper.
+        let sente = self.teban == Teban::Sente;
+        let rel = |(suji, dan): (usize, usize)| {
+            if sente {
+                (suji as i32, dan as i32)
+            } else {
+                (10 - suji as i32, 10 - dan as i32)
+            }
+        };
+        let (tx, ty) = rel(to);
+        // motions first and then sides among the rest.
+        for d in dir.chars().filter(|d| !matches!(d, 'R' | 'L')) {
+            cells.retain(|&c| {
+                let (x, y) = rel(c);
+                match d {
+                    'U' | 'A' => y > ty,
+                    'H' | 'S' | 'D' => y < ty,
+                    'Y' => y == ty,
+                    'C' => uses_choku(koma) && x == tx && y == ty + 1,
+                    _ => false,
+                }
+            });
+        }
+        for d in dir.chars().filter(|d| matches!(d, 'R' | 'L')) {
+            let xs: Vec<i32> = cells.iter().map(|&c| rel(c).0).collect();
+            let edge = if d == 'R' {
+                xs.iter().min()
+            } else {
+                xs.iter().max()
+            }
+            .copied();
+            cells.retain(|&c| Some(rel(c).0) == edge);
+        }
+        cells
+    }
+
+    /// Returns the position before a move.
+    /// a captured piece can not be known, so it is not put back.
+    ///
+    /// # Argument
+    /// * `mv` - the move which lead to this position.
+    /// # Return value
+    /// the position or None when the move doesn't fit.
+    pub fn unmove(&self, mv: &Move) -> Option<Position> {
+        let k = self.koma(mv.to).clone();
+        if k.is_blank() {
+            return None;
+        }
+        let mut pos = self.clone();
+        let teban = k.teban();
+        match mv.from {
+            Some(from) => {
+                if !self.koma(from).is_blank() || (mv.promote && !k.promotion().is_promoted()) {
+                    return None;
+                }
+                let promotion = if mv.promote {
+                    Promotion::None
+                } else {
+                    k.promotion()
+                };
+                pos.ban[from.1 - 1][9 - from.0] = Koma::new(k.komatype(), promotion, teban);
+            }
+            None => {
+                if k.komatype() != mv.drop || k.promotion().is_promoted() {
+                    return None;
+                }
+                let idx = TEGOMA.iter().position(|&t| t == mv.drop)?;
+                if teban == Teban::Sente {
+                    pos.sentegoma[idx] += 1;
+                } else {
+                    pos.gotegoma[idx] += 1;
+                }
+            }
+        }
+        pos.ban[mv.to.1 - 1][9 - mv.to.0] = Koma::from(' ', Promotion::None);
+        pos.teban = teban;
+        pos.nteme -= 1;
+        Some(pos)
+    }
+
+    /// Returns true if the piece at `from` can move to `to` on the board.
+    /// pieces on the way are taken into account, pieces at `to` are not.
+    ///
+    /// # Arguments
+    /// * `from` - cell index of the piece.
+    /// * `to` - cell index to go.
+    pub fn can_reach(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        let k = self.koma(from);
+        if k.is_blank() || from == to {
+            return false;
+        }
+        let dx = to.0 as i32 - from.0 as i32;
+        // forward is negative for both players.
+        let dy = if k.is_sente() {
+            to.1 as i32 - from.1 as i32
+        } else {
+            from.1 as i32 - to.1 as i32
+        };
+        let (steps, slides) = movements(k);
+        if steps.contains(&(dx, dy)) {
+            return true;
+        }
+        for (sx, sy) in slides {
+            let n = dx.abs().max(dy.abs());
+            if (sx * n, sy * n) != (dx, dy) {
+                continue;
+            }
+            let sy = if k.is_sente() { sy } else { -sy };
+            return (1..n).all(|i| {
+                let suji = (from.0 as i32 + sx * i) as usize;
+                let dan = (from.1 as i32 + sy * i) as usize;
+                self.koma((suji, dan)).is_blank()
+            });
+        }
+        false
+    }
+
+    /// apply a move.
+    ///
+    /// # Arguments
+    /// * `mv` - move to apply.
+    /// * `ply` - index in the move list. used for the error.
+    /// # Return value
+    /// () or SfenError.
+    pub fn apply(&mut self, mv: &Move, ply: usize) -> Result<(), SfenError> {
+        let err = |expected| SfenError::Move {
+            ply,
+            found: mv.to_usi(),
+            expected,
+        };
+        let teban = self.teban;
+        let target = self.koma(mv.to).clone();
+        match mv.from {
+            None => {
+                if !target.is_blank() {
+                    return Err(err("a drop to an empty cell"));
+                }
+                let idx = match TEGOMA.iter().position(|&k| k == mv.drop) {
+                    Some(idx) => idx,
+                    None => return Err(err("a drop of a piece in the hand")),
+                };
+                let tegoma = if teban == Teban::Sente {
+                    &mut self.sentegoma
+                } else {
+                    &mut self.gotegoma
+                };
+                if tegoma[idx] == 0 {
+                    return Err(err("a drop of a piece in the hand"));
+                }
+                tegoma[idx] -= 1;
+                self.ban[mv.to.1 - 1][9 - mv.to.0] = Koma::new(mv.drop, Promotion::None, teban);
+            }
+            Some(from) => {
+                let k = self.koma(from).clone();
+                if k.teban() != teban {
+                    return Err(err("a move of a piece of the player to move"));
+                }
+                if target.teban() == teban {
+                    return Err(err("a move to a cell w/o own piece"));
+                }
+                if !self.can_reach(from, mv.to) {
+                    return Err(err("a move the piece can make"));
+                }
+                if target.komatype() == KomaType::Gyoku {
+                    return Err(err("a move which doesn't capture a king"));
+                }
+                let zone = |dan: usize| {
+                    if teban == Teban::Sente {
+                        dan <= 3
+                    } else {
+                        dan >= 7
+                    }
+                };
+                if mv.promote
+                    && (!k.komatype().is_promotable()
+                        || k.promotion().is_promoted()
+                        || !(zone(from.1) || zone(mv.to.1)))
+                {
+                    return Err(err("a promotion in the zone of a promotable piece"));
+                }
+                if let Some(idx) = TEGOMA.iter().position(|&k| k == target.komatype()) {
+                    if teban == Teban::Sente {
+                        self.sentegoma[idx] += 1;
+                    } else {
+                        self.gotegoma[idx] += 1;
+                    }
+                }
+                let promotion = if mv.promote {
+                    Promotion::Promoted
+                } else {
+                    k.promotion()
+                };
+                self.ban[from.1 - 1][9 - from.0] = Koma::from(' ', Promotion::None);
+                self.ban[mv.to.1 - 1][9 - mv.to.0] = Koma::new(k.komatype(), promotion, teban);
+            }
+        }
+        self.teban = if teban == Teban::Sente {
+            Teban::Gote
+        } else {
+            Teban::Sente
+        };
+        self.nteme += 1;
+        Ok(())
+    }
+
+    /// Returns sfen text of the position.
+    pub fn to_sfen(&self) -> String {
+        let ban = self
+            .ban
+            .iter()
+            .map(|dan| {
+                let mut res = String::new();
+                let mut aki = 0;
+                for k in dan {
+                    if k.is_blank() {
+                        aki += 1;
+                        continue;
+                    }
+                    if aki > 0 {
+                        res += &aki.to_string();
+                        aki = 0;
+                    }
+                    res += &k.to_sfen();
+                }
+                if aki > 0 {
+                    res += &aki.to_string();
+                }
+                res
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+        let mut tegoma = String::new();
+        for (tgm, sente) in [(&self.sentegoma, true), (&self.gotegoma, false)] {
+            for (k, &n) in TEGOMA.iter().zip(tgm.iter()).rev() {
+                if n == 0 {
+                    continue;
+                }
+                if n > 1 {
+                    tegoma += &n.to_string();
+                }
+                let ch = k.to_sfen();
+                tegoma.push(if sente { ch } else { ch.to_ascii_lowercase() });
+            }
+        }
+        if tegoma.is_empty() {
+            tegoma = String::from("-");
+        }
+        format!(
+            "{} {} {} {}",
+            ban,
+            if self.teban == Teban::Sente { "b" } else { "w" },
+            tegoma,
+            self.nteme
+        )
+    }
+}
+
+/// directions of a piece. (suji, dan)
+type Directions = Vec<(i32, i32)>;
+
+/// Returns true if 直 is used for the piece.
+/// 飛, 角, 龍 and 馬 are told apart by the other words.
+///
+/// # Argument
+/// * `koma` - type of the piece.
+fn uses_choku(koma: KomaType) -> bool {
+    !matches!(koma, KomaType::Hisha | KomaType::Kaku)
+}
+
+/// Returns steps and sliding directions of a piece.
+/// forward is (0, -1) for both players.
+///
+/// # Argument
+/// * `k` - piece.
+fn movements(k: &Koma) -> (Directions, Directions) {
+    let kin = vec![(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (0, 1)];
+    let orth = vec![(0, -1), (-1, 0), (1, 0), (0, 1)];
+    let diag = vec![(-1, -1), (1, -1), (-1, 1), (1, 1)];
+    let promoted = k.promotion().is_promoted();
+    match k.komatype() {
+        KomaType::Fu | KomaType::Kyosha | KomaType::Keima | KomaType::Gin if promoted => {
+            (kin, Vec::new())
+        }
+        KomaType::Fu => (vec![(0, -1)], Vec::new()),
+        KomaType::Kyosha => (Vec::new(), vec![(0, -1)]),
+        KomaType::Keima => (vec![(-1, -2), (1, -2)], Vec::new()),
+        KomaType::Gin => (
+            vec![(-1, -1), (0, -1), (1, -1), (-1, 1), (1, 1)],
+            Vec::new(),
+        ),
+        KomaType::Kin => (kin, Vec::new()),
+        KomaType::Kaku => (if promoted { orth } else { Vec::new() }, diag),
+        KomaType::Hisha => (if promoted { diag } else { Vec::new() }, orth),
+        KomaType::Gyoku => ([orth, diag].concat(), Vec::new()),
+        KomaType::Aki => (Vec::new(), Vec::new()),
+    }
+}
+
+#[test]
+fn positiontest() {
+    let sfen = Sfen::new(STARTPOS).unwrap();
+    let mut pos = Position::from_sfen(&sfen).unwrap();
+    assert_eq!(pos.to_sfen(), STARTPOS);
+    assert!(pos.can_reach((7, 7), (7, 6)));
+    assert!(!pos.can_reach((7, 7), (7, 5)));
+    assert!(!pos.can_reach((8, 8), (2, 2)));
+    assert!(pos.can_reach((9, 9), (9, 8)));
+    assert!(pos.can_reach((2, 9), (1, 7)));
+    assert!(pos.can_reach((2, 9), (3, 7)));
+    assert!(!pos.can_reach((5, 5), (5, 4)));
+    for (i, usi) in ["7g7f", "3c3d", "8h2b+", "3a2b"].iter().enumerate() {
+        pos.apply(&Move::from_usi(usi, i + 1).unwrap(), i + 1)
+            .unwrap();
+    }
+    assert_eq!(
+        pos.to_sfen(),
+        "lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 5"
+    );
+    assert_eq!(pos.tegoma(Teban::Sente, KomaType::Kaku), 1);
+    assert_eq!(pos.tegoma(Teban::Gote, KomaType::Kaku), 1);
+    pos.apply(&Move::from_usi("B*4e", 5).unwrap(), 5).unwrap();
+    assert_eq!(
+        pos.to_sfen(),
+        "lnsgkg1nl/1r5s1/pppppp1pp/6p2/5B3/2P6/PP1PPPPPP/7R1/LNSGKGSNL w b 6"
+    );
+    assert_eq!(pos.teban(), Teban::Gote);
+
+    let errs = [
+        ("7g7f", "a move of a piece of the player to move"),
+        ("R*5e", "a drop of a piece in the hand"),
+        ("B*4e", "a drop to an empty cell"),
+        ("2b2a", "a move to a cell w/o own piece"),
+        ("8b8h", "a move the piece can make"),
+        ("3d3e+", "a promotion in the zone of a promotable piece"),
+    ];
+    for (usi, msg) in errs {
+        let mut p = pos.clone();
+        match p.apply(&Move::from_usi(usi, 6).unwrap(), 6) {
+            Err(SfenError::Move { expected, .. }) => assert_eq!(expected, msg),
+            _ => panic!("{}", usi),
+        }
+    }
+    for drop in [KomaType::Gyoku, KomaType::Aki] {
+        let mv = Move {
+            from: None,
+            to: (5, 5),
+            drop,
+            promote: false,
+        };
+        assert!(matches!(
+            pos.clone().apply(&mv, 6),
+            Err(SfenError::Move { ply: 6, .. })
+        ));
+    }
+
+    let sfen = Sfen::new("4k4/9/4P4/9/9/9/9/9/4K4 b - 1").unwrap();
+    let mut pos = Position::from_sfen(&sfen).unwrap();
+    assert!(pos.apply(&Move::from_usi("5c5a", 1).unwrap(), 1).is_err());
+    pos.apply(&Move::from_usi("5c5b+", 1).unwrap(), 1).unwrap();
+    assert_eq!(pos.to_sfen(), "4k4/4+P4/9/9/9/9/9/9/4K4 w - 2");
+    pos.apply(&Move::from_usi("5a5b", 2).unwrap(), 2).unwrap();
+    assert_eq!(pos.to_sfen(), "9/4k4/9/9/9/9/9/9/4K4 b p 3");
+}
+
+/// Returns true if the text looks like an USI position command.
+///
+/// # Argument
+/// * `txt` - text to check.
+pub fn is_usi(txt: &str) -> bool {
+    let txt = txt.trim_start();
+    txt.starts_with("position ") || txt.starts_with("startpos") || txt.starts_with("sfen ")
+}
+
+/// read USI position command like "position startpos moves 7g7f 3c3d".
+///
+/// # Arguments
+/// * `txt` - "position startpos moves ..." or "position sfen (sfen) moves ...".
+///   "position" can be omitted.
+/// * `ply` - number of moves to apply. all moves when None.
+/// # Return value
+/// position after the moves and the last applied move, or SfenError.
+pub fn read_usi(txt: &str, ply: Option<usize>) -> Result<(Sfen, Option<LastMove>), SfenError> {
+    let mut tokens = txt.split_whitespace().peekable();
+    if tokens.peek() == Some(&"position") {
+        tokens.next();
+    }
+    let sfen = match tokens.next() {
+        Some("startpos") => Sfen::new(STARTPOS)?,
+        Some("sfen") => {
+            let mut fields = Vec::new();
+            while let Some(t) = tokens.next_if(|t| *t != "moves") {
+                fields.push(t);
+            }
+            if fields.len() == 3 {
+                fields.push("1");
+            }
+            Sfen::new(&fields.join(" "))?
+        }
+        found => {
+            return Err(SfenError::Usi {
+                found: found.unwrap_or("").to_string(),
+                expected: "startpos or sfen",
+            })
+        }
+    };
+    match tokens.next() {
+        None => return Ok((sfen, None)),
+        Some("moves") => {}
+        Some(found) => {
+            return Err(SfenError::Usi {
+                found: found.to_string(),
+                expected: "moves",
+            })
+        }
+    }
+    let mut pos = Position::from_sfen(&sfen)?;
+    let mut lm = None;
+    for (i, usi) in tokens.take(ply.unwrap_or(usize::MAX)).enumerate() {
+        let mv = Move::from_usi(usi, i + 1)?;
+        let last = pos.lastmove(&mv);
+        pos.apply(&mv, i + 1)?;
+        lm = Some(last);
+    }
+    Ok((Sfen::new(&pos.to_sfen())?, lm))
+}
+
+/// Returns LastMove w/ the piece, promotion and direction
+/// from the position after the move.
+///
+/// # Arguments
+/// * `sfen` - position after the move.
+/// * `mv` - the move which lead to `sfen`.
+/// # Return value
+/// LastMove or SfenError.
+pub fn lastmove_after(sfen: &Sfen, mv: &Move) -> Result<LastMove, SfenError> {
+    match Position::from_sfen(sfen)?.unmove(mv) {
+        Some(before) => Ok(before.lastmove(mv)),
+        None => Err(SfenError::LastMove {
+            offset: 0,
+            found: mv.to_usi(),
+            expected: "a move which leads to the position",
+        }),
+    }
+}
+
+#[test]
+fn notationtest() {
+    let notation = |sfen: &str, usi: &str| {
+        let sfen = Sfen::new(sfen).unwrap();
+        let pos = Position::from_sfen(&sfen).unwrap();
+        let mv = Move::from_usi(usi, 1).unwrap();
+        let lm = pos.lastmove(&mv);
+        // the direction letters tell the piece.
+        if let Some(from) = mv.from.filter(|&from| pos.can_reach(from, mv.to)) {
+            let promoted = lm.koma.promotion().is_promoted();
+            let origins = pos.origins(mv.to, lm.koma.komatype(), promoted, &lm.dir);
+            assert_eq!(origins, vec![from], "{}", usi);
+        }
+        lm.to_string().unwrap()
+    };
+    assert_eq!(notation(STARTPOS, "7g7f"), "▲７六歩まで");
+    assert_eq!(notation(STARTPOS, "6i5h"), "▲５八金左まで");
+    assert_eq!(notation(STARTPOS, "4i5h"), "▲５八金右まで");
+    let golds = "4k4/9/9/9/9/9/9/9/3GG4 b - 1";
+    assert_eq!(notation(golds, "5i5h"), "▲５八金直まで");
+    assert_eq!(notation(golds, "6i5h"), "▲５八金左まで");
+    assert_eq!(notation(golds, "5i6h"), "▲６八金右まで");
+    assert_eq!(notation(golds, "6i6h"), "▲６八金直まで");
+    let golds = "4k4/9/9/9/9/9/9/5G3/3G5 b - 1";
+    assert_eq!(notation(golds, "4h5h"), "▲５八金寄まで");
+    assert_eq!(notation(golds, "6i5h"), "▲５八金上まで");
+    let golds = "4k4/9/9/9/9/9/9/9/3GGG3 b - 1";
+    assert_eq!(notation(golds, "4i5h"), "▲５八金右まで");
+    assert_eq!(notation(golds, "6i5h"), "▲５八金左まで");
+    assert_eq!(notation(golds, "5i5h"), "▲５八金直まで");
+    let gote = "3g1g3/9/9/9/9/9/9/9/4K4 w - 1";
+    assert_eq!(notation(gote, "6a5b"), "△５二金右まで");
+    assert_eq!(notation(gote, "4a5b"), "△５二金左まで");
+    let dragons = "4k4/9/9/9/+R7+R/9/9/9/4K4 b - 1";
+    assert_eq!(notation(dragons, "9e5e"), "▲５五龍左まで");
+    assert_eq!(notation(dragons, "1e5e"), "▲５五龍右まで");
+    let dragons = "4k4/9/4+R4/9/9/9/4+R4/9/4K4 b - 1";
+    assert_eq!(notation(dragons, "5c5e"), "▲５五龍引まで");
+    assert_eq!(notation(dragons, "5g5e"), "▲５五龍上まで");
+    let rooks = "4k4/9/9/9/9/9/9/8R/4R4 b - 1";
+    assert_eq!(notation(rooks, "5i5h"), "▲５八飛上まで");
+    assert_eq!(notation(rooks, "1h5h"), "▲５八飛寄まで");
+    let rooks = "4k4/9/9/9/9/9/9/R7R/4K4 b - 1";
+    assert_eq!(notation(rooks, "9h5h"), "▲５八飛左まで");
+    assert_eq!(notation(rooks, "1h5h"), "▲５八飛右まで");
+    let bishops = "4k4/9/9/9/9/9/9/9/3B1B2K b - 1";
+    assert_eq!(notation(bishops, "6i5h"), "▲５八角左まで");
+    assert_eq!(notation(bishops, "4i5h"), "▲５八角右まで");
+    // 直 is not used for 飛 and 角.
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/8R/4R4 b - 1").unwrap();
+    let pos = Position::from_sfen(&sfen).unwrap();
+    assert!(pos.origins((5, 8), KomaType::Hisha, false, "C").is_empty());
+    let silver = "4k4/9/9/6S2/9/9/9/9/4K4 b - 1";
+    assert_eq!(notation(silver, "3d2c"), "▲２三銀不成まで");
+    assert_eq!(notation(silver, "3d2c+"), "▲２三銀成まで");
+    assert_eq!(notation(silver, "3d3e"), "▲３五銀まで");
+    let drop = "4k4/9/9/9/9/9/9/9/4K4 b G 1";
+    assert_eq!(notation(drop, "G*5b"), "▲５二金打まで");
+
+    let after =
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2").unwrap();
+    let lm = lastmove_after(&after, &Move::from_usi("7g7f", 1).unwrap()).unwrap();
+    assert_eq!(lm.to_string().unwrap(), "▲７六歩まで");
+    let after =
+        Sfen::new("lnsgkgsnl/1r5+B1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL w B 4").unwrap();
+    let lm = lastmove_after(&after, &Move::from_usi("8h2b+", 1).unwrap()).unwrap();
+    assert_eq!(lm.to_string().unwrap(), "▲２二角成まで");
+    assert!(lastmove_after(&after, &Move::from_usi("8h2b", 1).unwrap()).is_ok());
+    assert!(lastmove_after(&after, &Move::from_usi("5e5d", 1).unwrap()).is_err());
+    let after = Sfen::new("4k4/4G4/9/9/9/9/9/9/4K4 w - 2").unwrap();
+    let lm = lastmove_after(&after, &Move::from_usi("G*5b", 1).unwrap()).unwrap();
+    assert!(lm.is_from_komadai());
+    assert_eq!(lm.to_string().unwrap(), "▲５二金打まで");
+    let after = Sfen::new("4k4/9/9/9/9/9/9/1+b7/4K4 b - 2").unwrap();
+    let lm = lastmove_after(&after, &Move::from_usi("2b8h+", 1).unwrap()).unwrap();
+    assert_eq!((lm.from, lm.to), ((2, 2), (8, 8)));
+    assert_eq!(lm.to_string().unwrap(), "△８八角成まで");
+}
+
+#[test]
+fn usitest() {
+    assert!(is_usi("position startpos"));
+    assert!(is_usi("startpos moves 7g7f"));
+    assert!(is_usi("sfen 9/9/9/9/9/9/9/9/9 b - 1"));
+    assert!(!is_usi(STARTPOS));
+
+    let (sfen, lm) = read_usi("position startpos", None).unwrap();
+    assert_eq!(Position::from_sfen(&sfen).unwrap().to_sfen(), STARTPOS);
+    assert!(lm.is_none());
+
+    let txt = "position startpos moves 7g7f 3c3d 8h2b+ 3a2b";
+    let (sfen, lm) = read_usi(txt, None).unwrap();
+    assert_eq!(
+        Position::from_sfen(&sfen).unwrap().to_sfen(),
+        "lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb 5"
+    );
+    let lm = lm.unwrap();
+    assert_eq!(lm.from, (3, 1));
+    assert_eq!(lm.to, (2, 2));
+    assert_eq!(lm.to_string().unwrap(), "△２二銀まで");
+
+    let (sfen, lm) = read_usi(txt, Some(3)).unwrap();
+    assert_eq!(
+        Position::from_sfen(&sfen).unwrap().to_sfen(),
+        "lnsgkgsnl/1r5+B1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL w B 4"
+    );
+    assert_eq!(lm.unwrap().to_string().unwrap(), "▲２二角成まで");
+
+    let txt = "sfen lnsgkg1nl/1r5s1/pppppp1pp/6p2/9/2P6/PP1PPPPPP/7R1/LNSGKGSNL b Bb moves B*4e";
+    let (sfen, lm) = read_usi(txt, None).unwrap();
+    assert_eq!(
+        Position::from_sfen(&sfen).unwrap().to_sfen(),
+        "lnsgkg1nl/1r5s1/pppppp1pp/6p2/5B3/2P6/PP1PPPPPP/7R1/LNSGKGSNL w b 2"
+    );
+    assert_eq!(lm.unwrap().to_string().unwrap(), "▲４五角打まで");
+
+    assert!(matches!(
+        read_usi("position kifu", None),
+        Err(SfenError::Usi { .. })
+    ));
+    assert!(matches!(
+        read_usi("position startpos 7g7f", None),
+        Err(SfenError::Usi { .. })
+    ));
+    assert!(matches!(
+        read_usi("position startpos moves 7g7f 7g7f", None),
+        Err(SfenError::Move { ply: 2, .. })
+    ));
+}
@@ -1,8 +1,11 @@
 use super::*;
+use decoration::Decoration;
 use regex::Regex;
 use svgbuilder::*;
+use theme::Theme;
 
 /// parts in sfen expression.
+#[derive(Clone)]
 pub struct Sfen {
     /// ban status.
     ban: String,
@@ -14,8 +17,288 @@ pub struct Sfen {
     nteme: i32,
 }
 
-/// which turn it is.
+/// error while reading sfen or last move.
+///
+/// `offset` is a character index in the field.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum SfenError {
+    /// not enough fields in sfen.
+    Sfen {
+        found: usize,
+        expected: &'static str,
+    },
+    /// invalid letter in the board field.
+    Board {
+        /// row number. 1 to 9.
+        dan: usize,
+        /// column number. 9 to 1, 0 when the row is too long.
+        suji: usize,
+        offset: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid turn field.
+    Turn {
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid letter in the hands field.
+    Hands {
+        offset: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid move number field.
+    MoveNumber {
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid last move.
+    LastMove {
+        offset: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid USI position command.
+    Usi {
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid line in a game record.
+    Kifu {
+        /// line number. 1 for the first line.
+        line: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid line in a board diagram.
+    Bod {
+        /// line number. 1 for the first line.
+        line: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// a move which can not be applied.
+    Move {
+        /// index in the move list. 1 for the first move.
+        ply: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid arrow.
+    Arrow {
+        /// index in the arrow list. 1 for the first arrow.
+        index: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid mark.
+    Mark {
+        /// index in the mark list. 1 for the first mark.
+        index: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid annotation.
+    Annotation {
+        /// index in the annotation list. 1 for the first annotation.
+        index: usize,
+        found: String,
+        expected: &'static str,
+    },
+    /// invalid query parameter like theme or width.
+    Param {
+        /// name of the parameter.
+        name: &'static str,
+        found: String,
+        expected: &'static str,
+    },
+}
+
+impl std::fmt::Display for SfenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SfenError::Sfen { found, expected } => {
+                write!(f, "sfen has {} fields, expected {}.", found, expected)
+            }
+            SfenError::Board {
+                dan,
+                suji,
+                offset,
+                found,
+                expected,
+            } => write!(
+                f,
+                "board: \"{}\" at dan {} suji {} (offset {}), expected {}.",
+                found, dan, suji, offset, expected
+            ),
+            SfenError::Turn { found, expected } => {
+                write!(f, "turn: \"{}\", expected {}.", found, expected)
+            }
+            SfenError::Hands {
+                offset,
+                found,
+                expected,
+            } => write!(
+                f,
+                "hands: \"{}\" at offset {}, expected {}.",
+                found, offset, expected
+            ),
+            SfenError::MoveNumber { found, expected } => {
+                write!(f, "move number: \"{}\", expected {}.", found, expected)
+            }
+            SfenError::LastMove {
+                offset,
+                found,
+                expected,
+            } => write!(
+                f,
+                "last move: \"{}\" at offset {}, expected {}.",
+                found, offset, expected
+            ),
+            SfenError::Usi { found, expected } => {
+                write!(f, "usi: \"{}\", expected {}.", found, expected)
+            }
+            SfenError::Kifu {
+                line,
+                found,
+                expected,
+            } => write!(
+                f,
+                "kifu: \"{}\" at line {}, expected {}.",
+                found, line, expected
+            ),
+            SfenError::Bod {
+                line,
+                found,
+                expected,
+            } => write!(
+                f,
+                "bod: \"{}\" at line {}, expected {}.",
+                found, line, expected
+            ),
+            SfenError::Move {
+                ply,
+                found,
+                expected,
+            } => write!(
+                f,
+                "move: \"{}\" at ply {}, expected {}.",
+                found, ply, expected
+            ),
+            SfenError::Arrow {
+                index,
+                found,
+                expected,
+            } => write!(
+                f,
+                "arrows: \"{}\" at index {}, expected {}.",
+                found, index, expected
+            ),
+            SfenError::Mark {
+                index,
+                found,
+                expected,
+            } => write!(
+                f,
+                "marks: \"{}\" at index {}, expected {}.",
+                found, index, expected
+            ),
+            SfenError::Annotation {
+                index,
+                found,
+                expected,
+            } => write!(
+                f,
+                "annotate: \"{}\" at index {}, expected {}.",
+                found, index, expected
+            ),
+            SfenError::Param {
+                name,
+                found,
+                expected,
+            } => write!(f, "{}: \"{}\", expected {}.", name, found, expected),
+        }
+    }
+}
+
+/// rule violation found in a position.
+#[derive(PartialEq, Debug, Clone, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Violation {
+    /// board doesn't have 9 rows.
+    Rows { found: usize },
+    /// a row doesn't have 9 cells.
+    Cells { dan: usize, found: usize },
+    /// two or more unpromoted pawns on a file.
+    Nifu { suji: usize, teban: Teban },
+    /// a piece which can not move any more.
+    DeadPiece {
+        suji: usize,
+        dan: usize,
+        /// piece in sfen expression. ex. "N"
+        koma: String,
+    },
+    /// too many pieces of a type on board and in hands.
+    PieceCount {
+        /// piece in sfen expression. ex. "P"
+        koma: String,
+        found: usize,
+        max: usize,
+    },
+    /// more than one king for a player.
+    Kings { teban: Teban, found: usize },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Violation::Rows { found } => write!(f, "board has {} rows.", found),
+            Violation::Cells { dan, found } => write!(f, "dan {} has {} cells.", dan, found),
+            Violation::Nifu { suji, teban } => {
+                write!(f, "{:?} has two or more pawns on suji {}.", teban, suji)
+            }
+            Violation::DeadPiece { suji, dan, koma } => {
+                write!(f, "{} at {}{} can not move.", koma, suji, dan)
+            }
+            Violation::PieceCount { koma, found, max } => {
+                write!(f, "{} {}s, {} at most.", found, koma, max)
+            }
+            Violation::Kings { teban, found } => write!(f, "{:?} has {} kings.", teban, found),
+        }
+    }
+}
+
+/// how to treat rule violations in a position.
 #[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Validation {
+    /// reject the position.
+    Strict,
+    /// render the position and report violations.
+    Warn,
+    /// don't check.
+    Off,
+}
+
+impl Validation {
+    /// Returns Validation or None for unknown text.
+    ///
+    /// # Argument
+    /// * `txt` - "strict", "warn" or "off".
+    pub fn from(txt: &str) -> Option<Validation> {
+        match txt {
+            "strict" => Some(Validation::Strict),
+            "warn" => Some(Validation::Warn),
+            "off" => Some(Validation::Off),
+            _ => None,
+        }
+    }
+}
+
+/// which turn it is.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Teban {
     /// black.
     Sente,
@@ -47,7 +330,7 @@ impl KomaType {
     ///
     /// # Return value
     /// koma expression in japanese. ex. "歩"
-    pub fn to_string(&self, promote: Promotion) -> String {
+    pub fn to_string(self, promote: Promotion) -> String {
         let idx = [
             KomaType::Fu,
             KomaType::Kyosha,
@@ -60,7 +343,7 @@ impl KomaType {
             KomaType::Aki,
         ]
         .iter()
-        .position(|&k| k == *self)
+        .position(|&k| k == self)
         .unwrap();
         match if promote.is_promoted() {
             "と杏圭全金馬龍玉"
@@ -75,6 +358,26 @@ impl KomaType {
         }
     }
 
+    /// Returns a letter in sfen expression for sente. ' ' for `Aki`.
+    pub fn to_sfen(self) -> char {
+        match self {
+            KomaType::Fu => 'P',
+            KomaType::Kyosha => 'L',
+            KomaType::Keima => 'N',
+            KomaType::Gin => 'S',
+            KomaType::Kin => 'G',
+            KomaType::Kaku => 'B',
+            KomaType::Hisha => 'R',
+            KomaType::Gyoku => 'K',
+            KomaType::Aki => ' ',
+        }
+    }
+
+    /// Returns true if the piece can be promoted.
+    pub fn is_promotable(self) -> bool {
+        !matches!(self, KomaType::Kin | KomaType::Gyoku | KomaType::Aki)
+    }
+
     /// Returns Komatype from a letter.
     ///
     /// # Argument
@@ -184,15 +487,19 @@ impl Promotion {
         *self == Promotion::Promoted
     }
     /// Returns true if it is `NotPromoted`.
+    #[allow(dead_code)]
     pub fn is_notpromoted(&self) -> bool {
         *self == Promotion::NotPromoted
     }
-    /// Returns `String` about promotion in a move.
-    pub fn to_string(&self) -> String {
+}
+
+impl std::fmt::Display for Promotion {
+    /// writes about promotion in a move.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Promotion::Promoted => String::from("成"),
-            Promotion::NotPromoted => String::from("不成"),
-            Promotion::None => String::new(),
+            Promotion::Promoted => write!(f, "成"),
+            Promotion::NotPromoted => write!(f, "不成"),
+            Promotion::None => Ok(()),
         }
     }
 }
@@ -200,16 +507,16 @@ impl Promotion {
 #[test]
 fn promotest() {
     let prm = Promotion::None;
-    assert_eq!(prm.is_promoted(), false);
-    assert_eq!(prm.is_notpromoted(), false);
+    assert!(!prm.is_promoted());
+    assert!(!prm.is_notpromoted());
     assert_eq!(prm.to_string(), "");
     let prm = Promotion::Promoted;
-    assert_eq!(prm.is_promoted(), true);
-    assert_eq!(prm.is_notpromoted(), false);
+    assert!(prm.is_promoted());
+    assert!(!prm.is_notpromoted());
     assert_eq!(prm.to_string(), "成");
     let prm = Promotion::NotPromoted;
-    assert_eq!(prm.is_promoted(), false);
-    assert_eq!(prm.is_notpromoted(), true);
+    assert!(!prm.is_promoted());
+    assert!(prm.is_notpromoted());
     assert_eq!(prm.to_string(), "不成");
 }
 
@@ -251,8 +558,8 @@ impl Koma {
     ///
     /// # Argument
     /// * `csa` - 2 letters in CSA format.
-    ///           FU, KY, KE, GI, KI, KA, HI, OU, TO, NY, NE, NG, UM, RY.
-    ///           GY is same as OU here.
+    ///   FU, KY, KE, GI, KI, KA, HI, OU, TO, NY, NE, NG, UM, RY.
+    ///   GY is same as OU here.
     pub fn fromcsa(csa: &str) -> Option<Koma> {
         let tbl = [
             ("FU", 'P', Promotion::None),
@@ -272,21 +579,9 @@ impl Koma {
             ("RY", 'R', Promotion::Promoted),
             ("GY", 'K', Promotion::None),
         ];
-        match tbl.iter().find(|e| e.0 == csa) {
-            Some((_csa, ch, prm)) => Some(Koma::from(*ch, *prm)),
-            None => None,
-        }
-    }
-
-    /// Returns expression w/ text format.
-    /// blank cell will be " ・".
-    pub fn to_string(&self) -> String {
-        if self.teban == Teban::None || self.koma == KomaType::Aki {
-            return String::from(" ・");
-        }
-
-        String::from(if self.teban == Teban::Sente { " " } else { "v" })
-            + &self.koma.to_string(self.promotion)
+        tbl.iter()
+            .find(|e| e.0 == csa)
+            .map(|(_csa, ch, prm)| Koma::from(*ch, *prm))
     }
 
     /// Returns one letter japanese expression, like "龍".
@@ -297,6 +592,56 @@ impl Koma {
         Some(self.koma.to_string(self.promotion))
     }
 
+    /// Returns sfen expression like "+p" or empty string for blank.
+    pub fn to_sfen(&self) -> String {
+        if self.is_blank() {
+            return String::new();
+        }
+        let ch = self.koma.to_sfen();
+        format!(
+            "{}{}",
+            if self.promotion.is_promoted() {
+                "+"
+            } else {
+                ""
+            },
+            if self.is_gote() {
+                ch.to_ascii_lowercase()
+            } else {
+                ch
+            }
+        )
+    }
+
+    /// Returns `Koma`.
+    ///
+    /// # Arguments
+    /// * `koma` - type of a piece.
+    /// * `promotion` - promoted or not.
+    /// * `teban` - black or white.
+    pub fn new(koma: KomaType, promotion: Promotion, teban: Teban) -> Koma {
+        Koma {
+            koma,
+            promotion,
+            teban,
+        }
+    }
+
+    /// Returns type of the piece.
+    pub fn komatype(&self) -> KomaType {
+        self.koma
+    }
+
+    /// Returns promoted or not.
+    pub fn promotion(&self) -> Promotion {
+        self.promotion
+    }
+
+    /// Returns black or white.
+    pub fn teban(&self) -> Teban {
+        self.teban
+    }
+
     /// Returns true when it is blank(`KomaType::Aki`).
     pub fn is_blank(&self) -> bool {
         self.koma == KomaType::Aki
@@ -313,6 +658,23 @@ impl Koma {
     }
 }
 
+impl std::fmt::Display for Koma {
+    /// writes expression w/ text format.
+    /// blank cell will be " ・".
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.teban == Teban::None || self.koma == KomaType::Aki {
+            return write!(f, " ・");
+        }
+
+        write!(
+            f,
+            "{}{}",
+            if self.teban == Teban::Sente { " " } else { "v" },
+            self.koma.to_string(self.promotion)
+        )
+    }
+}
+
 #[test]
 fn komatest() {
     let prm = Promotion::None;
@@ -613,7 +975,17 @@ impl Tegoma {
         if self.num == 0 {
             return Ok(String::new());
         }
-        Ok(kanji + &kanjinum[self.num])
+        Ok(kanji + kanjinum[self.num])
+    }
+
+    /// Returns type of the piece.
+    pub fn komatype(&self) -> KomaType {
+        self.koma
+    }
+
+    /// Returns number of the pieces.
+    pub fn num(&self) -> usize {
+        self.num
     }
 }
 
@@ -639,19 +1011,27 @@ fn testegoma() {
 
 /// read text for a dan(row).
 ///
-/// # Argument
+/// # Arguments
 /// * `txt` - text for a dan in sfen format.
+/// * `dan` - row number. 1 to 9.
+/// * `offset` - index of the first letter of `txt` in the board field.
 ///
 /// # Return value
-/// a list(vector) of `Koma` or error message.
-fn extractdan(txt: &str) -> Result<Vec<Koma>, String> {
+/// a list(vector) of `Koma` or `SfenError::Board`.
+fn extractdan(txt: &str, dan: usize, offset: usize) -> Result<Vec<Koma>, SfenError> {
     let mut res = Vec::<Koma>::new();
-    let masu = txt.chars();
     let mut promote = Promotion::None;
     let rekoma = Regex::new("[PLNSGBRK]").unwrap();
-    for ch in masu {
+    let err = |res: &Vec<Koma>, i: usize, found: String, expected| SfenError::Board {
+        dan,
+        suji: 9_usize.saturating_sub(res.len()),
+        offset: offset + i,
+        found,
+        expected,
+    };
+    for (i, ch) in txt.chars().enumerate() {
         match ch {
-            '1'..='9' => {
+            '1'..='9' if !promote.is_promoted() => {
                 res.append(&mut vec![
                     Koma::from('?', Promotion::None);
                     ch.to_digit(10).unwrap() as usize
@@ -661,92 +1041,248 @@ fn extractdan(txt: &str) -> Result<Vec<Koma>, String> {
                 res.push(Koma::from(ch, promote));
                 promote = Promotion::None;
             }
-            '+' => promote = Promotion::Promoted,
-            _ => return Err(format!("{} is not allowed to use!!", ch)),
+            '+' if !promote.is_promoted() => promote = Promotion::Promoted,
+            _ if promote.is_promoted() => {
+                return Err(err(&res, i, ch.to_string(), "a piece after '+'"));
+            }
+            _ => {
+                return Err(err(
+                    &res,
+                    i,
+                    ch.to_string(),
+                    "one of 1-9, +, PLNSGBRK or plnsgbrk",
+                ))
+            }
         }
     }
+    if promote.is_promoted() {
+        return Err(err(
+            &res,
+            txt.chars().count(),
+            String::new(),
+            "a piece after '+'",
+        ));
+    }
     Ok(res)
 }
 
 impl Sfen {
-    /// Returns Sfen.
+    /// Returns Sfen or SfenError.
     ///
     /// # Argument
     /// * `text` - sfen.
-    pub fn new(text: &str) -> Sfen {
-        let e: Vec<&str> = text.split(" ").collect();
+    pub fn new(text: &str) -> Result<Sfen, SfenError> {
+        let e: Vec<&str> = text.split(' ').collect();
         if e.len() < 4 {
-            return Sfen {
-                ban: String::new(),
-                teban: String::new(),
-                tegoma: String::new(),
-                nteme: -2,
-            };
+            return Err(SfenError::Sfen {
+                found: e.len(),
+                expected: "4 fields, board turn hands move-number",
+            });
         }
-        Sfen {
+        let sfen = Sfen {
             ban: e[0].to_string(),
             teban: e[1].to_string(),
             tegoma: e[2].to_string(),
-            nteme: e[3].parse().unwrap_or(-1),
+            nteme: match e[3].parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    return Err(SfenError::MoveNumber {
+                        found: e[3].to_string(),
+                        expected: "a number",
+                    })
+                }
+            },
+        };
+        sfen.extractban()?;
+        if sfen.tebanexp().is_err() {
+            return Err(SfenError::Turn {
+                found: sfen.teban,
+                expected: "b or w",
+            });
+        }
+        sfen.extracttegoma()?;
+        Ok(sfen)
+    }
+
+    /// Returns which turn it is.
+    pub fn teban(&self) -> Teban {
+        match self.teban.as_str() {
+            "b" => Teban::Sente,
+            "w" => Teban::Gote,
+            _ => Teban::None,
         }
     }
 
+    /// Returns number of nth move.
+    pub fn nteme(&self) -> i32 {
+        self.nteme
+    }
+
     /// Returns teban expression in japanese or error message.
     fn tebanexp(&self) -> Result<String, String> {
-        if self.teban == "b" {
-            return Ok(String::from("先手の番です。"));
-        }
-        if self.teban == "w" {
-            return Ok(String::from("後手の番です。"));
+        match turnexp(&self.teban) {
+            Some(exp) if self.teban != "d" => Ok(String::from(exp)),
+            _ => Err(format!("{} is invalid teban expression.", self.teban)),
         }
-        if self.teban == "fb" {
-            return Ok(String::from("先手の勝ちです。"));
-        }
-        if self.teban == "fw" {
-            return Ok(String::from("後手の勝ちです。"));
-        }
-        Err(format!("{} is invalid teban expression.", self.teban))
     }
 
-    /// Returns array of Koma on board or error message.
-    pub fn extractban(&self) -> Result<Vec<Vec<Koma>>, String> {
+    /// Returns array of Koma on board or SfenError.
+    pub fn extractban(&self) -> Result<Vec<Vec<Koma>>, SfenError> {
         let mut masus: Vec<Vec<Koma>> = Vec::new();
-        let vdan: Vec<&str> = self.ban.split("/").collect();
-        for e in vdan.iter() {
-            match extractdan(e) {
-                Ok(ret) => masus.push(ret),
-                Err(msg) => return Err(msg),
-            }
+        let mut offset = 0;
+        for (i, e) in self.ban.split('/').enumerate() {
+            masus.push(extractdan(e, i + 1, offset)?);
+            offset += e.chars().count() + 1;
         }
-        return Ok(masus);
+        Ok(masus)
     }
 
-    /// Returns tuple of Tegomas or error message.
-    fn extracttegoma(&self) -> Result<(Vec<Tegoma>, Vec<Tegoma>), String> {
+    /// Returns tuple of Tegomas or SfenError.
+    pub fn extracttegoma(&self) -> Result<(Vec<Tegoma>, Vec<Tegoma>), SfenError> {
         let resente = Regex::new("[PLNSGBRK]").unwrap();
         let regote = Regex::new("[plnsgbrk]").unwrap();
         let mut sentegoma = Vec::new();
         let mut gotegoma = Vec::new();
         let mut num = 0;
-        for ch in self.tegoma.chars() {
+        for (i, ch) in self.tegoma.chars().enumerate() {
             match ch {
-                '1'..='9' => num = num * 10 + ch.to_digit(10).unwrap(),
+                '0'..='9' => num = num * 10 + ch.to_digit(10).unwrap(),
                 ch if resente.is_match(&ch.to_string()) => {
-                    sentegoma.push(Tegoma::new(ch.to_ascii_lowercase(), num as usize));
+                    sentegoma.push(Tegoma::new(ch.to_ascii_lowercase(), num.max(1) as usize));
                     num = 0;
                 }
                 ch if regote.is_match(&ch.to_string()) => {
-                    gotegoma.push(Tegoma::new(ch, num as usize));
-                    // gotegoma = gotegoma + &p2fu(ch, Promote::None) + &kanjinum(num as usize).unwrap();
+                    gotegoma.push(Tegoma::new(ch, num.max(1) as usize));
                     num = 0;
                 }
-                '-' => break,
-                _ => return Err(format!("{} is not allowed to use!!", ch)),
+                '-' if self.tegoma == "-" => break,
+                _ => {
+                    return Err(SfenError::Hands {
+                        offset: i,
+                        found: ch.to_string(),
+                        expected: if num > 0 {
+                            "a piece after a number"
+                        } else {
+                            "-, a number, PLNSGBRK or plnsgbrk"
+                        },
+                    })
+                }
             }
         }
+        if num > 0 {
+            return Err(SfenError::Hands {
+                offset: self.tegoma.chars().count(),
+                found: String::new(),
+                expected: "a piece after a number",
+            });
+        }
         Ok((sentegoma, gotegoma))
     }
 
+    /// check the position against the rules.
+    ///
+    /// # Return value
+    /// list of violations or SfenError.
+    pub fn validate(&self) -> Result<Vec<Violation>, SfenError> {
+        let ban = self.extractban()?;
+        let (sentegoma, gotegoma) = self.extracttegoma()?;
+        let mut res = Vec::new();
+
+        if ban.len() != 9 {
+            res.push(Violation::Rows { found: ban.len() });
+        }
+        for (i, dan) in ban.iter().enumerate() {
+            if dan.len() != 9 {
+                res.push(Violation::Cells {
+                    dan: i + 1,
+                    found: dan.len(),
+                });
+            }
+        }
+
+        // nifu
+        for teban in [Teban::Sente, Teban::Gote] {
+            for j in 0..9 {
+                let nfu = ban
+                    .iter()
+                    .filter_map(|dan| dan.get(j))
+                    .filter(|k| {
+                        k.teban == teban && k.koma == KomaType::Fu && !k.promotion.is_promoted()
+                    })
+                    .count();
+                if nfu > 1 {
+                    res.push(Violation::Nifu { suji: 9 - j, teban });
+                }
+            }
+        }
+
+        // pieces which can not move. rows and cells out of 9x9 are reported above.
+        for (i, dan) in ban.iter().enumerate().take(9) {
+            for (j, k) in dan.iter().enumerate().take(9) {
+                if k.is_blank() || k.promotion.is_promoted() {
+                    continue;
+                }
+                let rank = if k.is_sente() { i + 1 } else { 9 - i };
+                let dead = match k.koma {
+                    KomaType::Fu | KomaType::Kyosha => rank <= 1,
+                    KomaType::Keima => rank <= 2,
+                    _ => false,
+                };
+                if dead {
+                    res.push(Violation::DeadPiece {
+                        suji: 9 - j,
+                        dan: i + 1,
+                        koma: k.to_sfen(),
+                    });
+                }
+            }
+        }
+
+        // number of pieces
+        let komas = [
+            ('P', KomaType::Fu, 18),
+            ('L', KomaType::Kyosha, 4),
+            ('N', KomaType::Keima, 4),
+            ('S', KomaType::Gin, 4),
+            ('G', KomaType::Kin, 4),
+            ('B', KomaType::Kaku, 2),
+            ('R', KomaType::Hisha, 2),
+            ('K', KomaType::Gyoku, 2),
+        ];
+        for (ch, koma, max) in komas {
+            let onboard = ban.iter().flatten().filter(|k| k.koma == koma).count();
+            let inhands: usize = sentegoma
+                .iter()
+                .chain(gotegoma.iter())
+                .filter(|t| t.koma == koma)
+                .map(|t| t.num)
+                .sum();
+            if onboard + inhands > max {
+                res.push(Violation::PieceCount {
+                    koma: ch.to_string(),
+                    found: onboard + inhands,
+                    max,
+                });
+            }
+        }
+
+        // kings
+        for teban in [Teban::Sente, Teban::Gote] {
+            let nking = ban
+                .iter()
+                .flatten()
+                .filter(|k| k.teban == teban && k.koma == KomaType::Gyoku)
+                .count();
+            if nking > 1 {
+                res.push(Violation::Kings {
+                    teban,
+                    found: nking,
+                });
+            }
+        }
+        Ok(res)
+    }
+
     /// dump in BOD format.
     ///
     /// # Arguments.
@@ -754,74 +1290,76 @@ impl Sfen {
     /// * `gn` - gote's name.
     /// * `title` - title.
     /// * `lm` - last move.
+    /// * `turn` - b, w, fb, fw or d. the turn in sfen if None.
+    ///   the result is put instead of the last move for fb, fw and d.
     /// # Return value
-    /// BOD format text.
-    pub fn dump(&self, sn: &str, gn: &str, title: &str, lm: LastMove) -> String {
+    /// BOD format text or SfenError.
+    pub fn dump(
+        &self,
+        sn: &str,
+        gn: &str,
+        title: &str,
+        lm: LastMove,
+        turn: Option<&str>,
+    ) -> Result<String, SfenError> {
         let border = "+---------------------------+\n";
         let dannum = "一二三四五六七八九";
         let mut res = format!("  ９ ８ ７ ６ ５ ４ ３ ２ １\n{}", border);
-        let vdan: Vec<&str> = self.ban.split("/").collect();
-        for (i, e) in vdan.iter().enumerate() {
-            match extractdan(e) {
-                Ok(ret) => {
-                    res = format!(
-                        "{}|{}|{}\n",
-                        res,
-                        ret.iter()
-                            .map(|koma| koma.to_string())
-                            .collect::<Vec<String>>()
-                            .join(""),
-                        dannum.chars().nth(i).unwrap()
-                    );
-                }
-                Err(msg) => return format!("error in [{}]:{}", e, msg),
-            }
-            // match dumpextractdan(e) {
-            //     Ok(ret) => res = res + &ret + &dannum.chars().nth(i).unwrap().to_string() + "\n",
-            // }
-        }
-        match self.extracttegoma() {
-            Ok((sentegoma, gotegoma)) => {
-                let tgmsen = if sentegoma.is_empty() {
-                    String::from("なし")
-                } else {
-                    sentegoma
-                        .iter()
-                        .map(|t| t.to_kanji().unwrap())
-                        .collect::<Vec<String>>()
-                        .join("")
-                };
-                let tgmgo = if gotegoma.is_empty() {
-                    String::from("なし")
-                } else {
-                    gotegoma
-                        .iter()
-                        .map(|t| t.to_kanji().unwrap())
-                        .collect::<Vec<String>>()
-                        .join("")
-                };
-                res = format!(
-                    "後手：{}\n後手の持駒：{}\n{}{}先手の持駒：{}\n先手：{}\n",
-                    gn, tgmgo, res, border, tgmsen, sn
-                )
-            }
-            Err(msg) => return format!("error in [{}]:{}", self.tegoma, msg),
+        for (i, ret) in self.extractban()?.iter().enumerate() {
+            res = format!(
+                "{}|{}|{}\n",
+                res,
+                ret.iter()
+                    .map(|koma| koma.to_string())
+                    .collect::<Vec<String>>()
+                    .join(""),
+                dannum.chars().nth(i).unwrap_or('?')
+            );
         }
-        if lm.is_ok() {
-            match lm.to_string() {
-                Ok(msg) => {
-                    return res + &format!("手数＝{}　{}\n* {}", self.nteme, msg, title);
-                }
-                Err(msg) => msg,
-            }
+        let (sentegoma, gotegoma) = self.extracttegoma()?;
+        let hands = |found| SfenError::Hands {
+            offset: 0,
+            found,
+            expected: "up to 18 pieces of a type in a hand",
+        };
+        let tgmsen = if sentegoma.is_empty() {
+            String::from("なし")
         } else {
-            match self.tebanexp() {
-                Ok(msg) => {
-                    return res + &format!("手数＝{}　{}\n* {}", self.nteme, msg, title);
-                }
-                Err(msg) => msg,
+            sentegoma
+                .iter()
+                .map(|t| t.to_kanji().map_err(hands))
+                .collect::<Result<Vec<String>, SfenError>>()?
+                .join("")
+        };
+        let tgmgo = if gotegoma.is_empty() {
+            String::from("なし")
+        } else {
+            gotegoma
+                .iter()
+                .map(|t| t.to_kanji().map_err(hands))
+                .collect::<Result<Vec<String>, SfenError>>()?
+                .join("")
+        };
+        res = format!(
+            "後手：{}\n後手の持駒：{}\n{}{}先手の持駒：{}\n先手：{}\n",
+            gn, tgmgo, res, border, tgmsen, sn
+        );
+        let turn = turn.unwrap_or(&self.teban);
+        let msg = match turnexp(turn) {
+            Some(exp) if !lm.is_ok() || ["fb", "fw", "d"].contains(&turn) => String::from(exp),
+            Some(_) => lm.to_string().map_err(|_| SfenError::LastMove {
+                offset: 0,
+                found: format!("{:?}", lm.to),
+                expected: "a move which can be written in japanese",
+            })?,
+            None => {
+                return Err(SfenError::Turn {
+                    found: turn.to_string(),
+                    expected: "b, w, fb, fw or d",
+                })
             }
-        }
+        };
+        Ok(res + &format!("手数＝{}　{}\n* {}", self.nteme, msg, title))
     }
 
     /// build svg tag to point out last move cell.
@@ -829,22 +1367,22 @@ impl Sfen {
     /// # Arguments
     /// * `suji` - column number.
     /// * 'dan' - row number.
+    /// * `flip` - seen from gote.
+    /// * `theme` - colors.
     /// # Return value
     /// SVG tag.
-    fn build_lastmove(&self, suji: usize, dan: usize) -> Tag {
+    fn build_lastmove(&self, suji: usize, dan: usize, flip: bool, theme: &Theme) -> Tag {
         let mut glm = Tag::new("g");
         glm.newattrib("id", "lastmove");
-        glm.newattrib(
-            "transform",
-            &format!("translate({}, {})", 180 - suji * 20, dan * 20 - 20),
-        );
+        let (x, y) = cellpos((suji, dan), flip);
+        glm.addattrib(Attrib::new("transform", format!("translate({}, {})", x, y)));
         let mut rect = Tag::new("rect");
         let atr = [
             ("x", "0"),
             ("y", "0"),
             ("width", "20"),
             ("height", "20"),
-            ("fill", "#FF4"),
+            ("fill", theme.lastmove.as_str()),
         ];
         for (nm, val) in atr {
             rect.newattrib(nm, val);
@@ -853,67 +1391,265 @@ impl Sfen {
         glm
     }
 
-    /// build board svg tag.
+    /// build svg tag to highlight the cell where the last move came from.
+    ///
+    /// # Arguments
+    /// * `suji` - column number.
+    /// * 'dan' - row number.
+    /// * `flip` - seen from gote.
+    /// * `theme` - colors.
+    /// # Return value
+    /// SVG tag.
+    fn build_lastmovefrom(&self, suji: usize, dan: usize, flip: bool, theme: &Theme) -> Tag {
+        let mut glm = Tag::new("g");
+        glm.newattrib("id", "lastmovefrom");
+        let (x, y) = cellpos((suji, dan), flip);
+        glm.addattrib(Attrib::new("transform", format!("translate({}, {})", x, y)));
+        let mut rect = Tag::new("rect");
+        let atr = [
+            ("x", "0"),
+            ("y", "0"),
+            ("width", "20"),
+            ("height", "20"),
+            ("fill", theme.lastmovefrom.as_str()),
+        ];
+        for (nm, val) in atr {
+            rect.newattrib(nm, val);
+        }
+        glm.addchild(rect);
+        glm
+    }
+
+    /// build svg tag to paint cells.
     ///
     /// # Argument
-    /// * `lastmove` - cell index.
+    /// * `marks` - cells and colors.
+    /// * `flip` - seen from gote.
+    /// # Return value
+    /// SVG tag.
+    fn build_marks(&self, marks: &[decoration::Mark], flip: bool) -> Tag {
+        let mut gmarks = Tag::new("g");
+        gmarks.newattrib("id", "marks");
+        for mark in marks {
+            let (x, y) = cellpos(mark.pos, flip);
+            let mut rect = Tag::new("rect");
+            rect.addattrib(Attrib::new("x", format!("{}", x)));
+            rect.addattrib(Attrib::new("y", format!("{}", y)));
+            rect.newattrib("width", "20");
+            rect.newattrib("height", "20");
+            rect.newattrib("fill", &mark.color);
+            if mark.opacity < 1.0 {
+                rect.addattrib(Attrib::new("fill-opacity", format!("{}", mark.opacity)));
+            }
+            gmarks.addchild(rect);
+        }
+        gmarks
+    }
+
+    /// build svg tag to put glyphs on cells.
+    ///
+    /// # Argument
+    /// * `annotations` - cells, glyphs and where to put.
+    /// * `flip` - seen from gote.
+    /// * `color` - color of the glyphs.
+    /// # Return value
+    /// SVG tag.
+    fn build_annotations(
+        &self,
+        annotations: &[decoration::Annotation],
+        flip: bool,
+        color: &str,
+    ) -> Tag {
+        let mut gann = Tag::new("g");
+        gann.newattrib("id", "annotations");
+        gann.newattrib("fill", color);
+        for ann in annotations {
+            let (cx, cy) = cellpos(ann.pos, flip);
+            let (x, y, size, anchor) = ann.corner.layout();
+            let mut tag = Tag::new("text");
+            tag.addattrib(Attrib::new("x", format!("{}", cx + x)));
+            tag.addattrib(Attrib::new("y", format!("{}", cy + y)));
+            tag.newattrib("font-size", size);
+            tag.newattrib("text-anchor", anchor);
+            tag.value = ann.glyph.clone();
+            gann.addchild(tag);
+        }
+        gann
+    }
+
+    /// Returns where a piece in hand is in the coordinates of the board.
+    ///
+    /// # Arguments
+    /// * `teban` - whose hand.
+    /// * `koma` - piece in hand. the mark is used if it's not in hand.
+    /// * `flip` - seen from gote.
+    /// # Return value
+    /// x and y coordinates or SfenError.
+    fn handpos(&self, teban: Teban, koma: KomaType, flip: bool) -> Result<(f64, f64), SfenError> {
+        let (sentegoma, gotegoma) = self.extracttegoma()?;
+        let (right, tegoma) = match teban {
+            Teban::Gote => (flip, gotegoma),
+            _ => (!flip, sentegoma),
+        };
+        let x = if right { 204.0 } else { -26.0 };
+        match tegoma.iter().position(|t| t.komatype() == koma) {
+            Some(i) => Ok((x, i as f64 * 20.0 + 24.0)),
+            None => Ok((x, 3.0)),
+        }
+    }
+
+    /// build board svg tag.
+    ///
+    /// # Arguments
+    /// * `lastmove` - last move to highlight.
+    /// * `deco` - arrows and so on to draw over the board.
     /// # Return value
-    /// SVG tag or error message.
-    fn buildboard(&self, lastmove: Option<(usize, usize)>) -> Result<Tag, String> {
+    /// SVG tag or SfenError.
+    fn buildboard(&self, lastmove: &LastMove, deco: &Decoration) -> Result<Tag, SfenError> {
         match self.extractban() {
             Ok(ban) => {
                 let mut gban = Tag::new("g");
                 gban.newattrib("id", "board");
                 gban.newattrib("transform", "translate(35,65)");
 
-                if lastmove.is_some() {
-                    let lm = lastmove.unwrap();
-                    let lm = self.build_lastmove(lm.0, lm.1);
-                    gban.addchild(lm);
+                let flip = deco.flip;
+                let theme = &deco.theme;
+                let frompos = lastmove.frompos();
+                if let Some((suji, dan)) = frompos {
+                    gban.addchild(self.build_lastmovefrom(suji, dan, flip, theme));
+                }
+                if let Some((suji, dan)) = lastmove.topos() {
+                    gban.addchild(self.build_lastmove(suji, dan, flip, theme));
+                }
+                if !deco.marks.is_empty() {
+                    gban.addchild(self.build_marks(&deco.marks, flip));
                 }
 
-                gban.addchild(banborder());
+                gban.addchild(banborder(flip, theme));
 
                 for (i, dan) in ban.iter().enumerate() {
+                    let y = if flip { 8 - i as i32 } else { i as i32 };
                     let mut gdan = Tag::new("g");
                     gdan.addattrib(Attrib::new("id", format!("dan{}", i + 1)));
-                    gdan.addattrib(Attrib::new("transform", format!("translate(0,{})", i * 20)));
+                    gdan.addattrib(Attrib::new("transform", format!("translate(0,{})", y * 20)));
                     for (j, k) in dan.iter().enumerate() {
-                        match komatag(k, j as i32, 0) {
-                            Some(tag) => gdan.addchild(tag),
-                            None => {}
+                        let x = if flip { 8 - j as i32 } else { j as i32 };
+                        if let Some(tag) = komatag(k, x, 0, flip, theme) {
+                            gdan.addchild(tag);
                         }
                     }
                     if gdan.has_child() {
                         gban.addchild(gdan)
                     }
                 }
+                if !deco.annotations.is_empty() {
+                    gban.addchild(self.build_annotations(
+                        &deco.annotations,
+                        flip,
+                        &deco.theme.annotation,
+                    ));
+                }
+                let center = |pos: (usize, usize)| {
+                    let (x, y) = cellpos(pos, flip);
+                    (x as f64 + 10.0, y as f64 + 10.0)
+                };
+                if let (true, Some(from), Some(to)) = (deco.lmarrow, frompos, lastmove.topos()) {
+                    let mut tag = arrowtag(center(from), center(to), &deco.theme.arrow);
+                    tag.newattrib("id", "lastmovearrow");
+                    gban.addchild(tag);
+                }
+                if !deco.arrows.is_empty() {
+                    let mut garrows = Tag::new("g");
+                    garrows.newattrib("id", "arrows");
+                    // drops are for the player to move.
+                    let teban = self.teban();
+                    for arrow in deco.arrows.iter() {
+                        let from = match arrow.from {
+                            Some(from) => center(from),
+                            None => self.handpos(teban, arrow.drop, flip)?,
+                        };
+                        garrows.addchild(arrowtag(from, center(arrow.to), &arrow.color));
+                    }
+                    gban.addchild(garrows);
+                }
                 Ok(gban)
             }
             Err(msg) => Err(msg),
         }
     }
 
+    /// build svg tag to highlight a piece in hand.
+    ///
+    /// # Argument
+    /// * `y` - y coordinate of the bottom of the piece.
+    /// * `theme` - colors.
+    /// # Return value
+    /// SVG tag.
+    fn build_lastmovehand(&self, y: i32, theme: &Theme) -> Tag {
+        let mut rect = Tag::new("rect");
+        rect.newattrib("id", "lastmovefrom");
+        let atr = [
+            ("x", "-10"),
+            ("width", "20"),
+            ("height", "20"),
+            ("fill", theme.lastmovefrom.as_str()),
+        ];
+        for (nm, val) in atr {
+            rect.newattrib(nm, val);
+        }
+        rect.addattrib(Attrib::new("y", format!("{}", y - 16)));
+        rect
+    }
+
     /// build svg tag about pieces in hands.
     ///
+    /// # Argument
+    /// * `drop` - player and piece of the last move if it was a drop.
+    ///   the piece is highlighted, or the mark if no more pieces of the type are left.
+    /// * `flip` - seen from gote. sente's hand is put on the left.
+    /// * `theme` - colors.
     /// # Return value
-    /// SVG tag or error message.
-    pub fn buildtegoma(&self) -> Result<(Tag, Tag), String> {
+    /// SVG tag or SfenError.
+    pub fn buildtegoma(
+        &self,
+        drop: Option<(Teban, KomaType)>,
+        flip: bool,
+        theme: &Theme,
+    ) -> Result<(Tag, Tag), SfenError> {
         match self.extracttegoma() {
             Ok((sentegoma, gotegoma)) => {
                 let mut st = Tag::new("g");
                 st.newattrib("id", "stegoma");
-                st.newattrib("transform", "translate(239,75)");
+                st.newattrib(
+                    "transform",
+                    if flip {
+                        "translate(9,75)"
+                    } else {
+                        "translate(239,75)"
+                    },
+                );
+                st.newattrib("fill", &theme.sente);
                 let mut tt = Tag::new("g");
                 tt.newattrib("transform", "translate(0,-7)");
                 let mut poly = Tag::new("polygon");
                 poly.newattrib("points", "0,-5 4,-4 5,5 -5,5 -4,-4");
-                poly.newattrib("fill", "black");
-                poly.newattrib("stroke", "black");
+                poly.newattrib("fill", &theme.fg);
+                poly.newattrib("stroke", &theme.fg);
                 tt.addchild(poly);
+                let mut dropped = match drop {
+                    Some((Teban::Sente, koma)) => Some(koma),
+                    _ => None,
+                };
+                if dropped.is_some() && !sentegoma.iter().any(|t| Some(t.koma) == dropped) {
+                    st.addchild(self.build_lastmovehand(-1, theme));
+                }
                 st.addchild(tt);
                 let mut y = 20;
                 for tgm in sentegoma {
+                    if dropped == Some(tgm.koma) {
+                        st.addchild(self.build_lastmovehand(y, theme));
+                        dropped = None;
+                    }
                     let mut tag = Tag::new("text");
                     let atr = [("x", "0"), ("font-size", "16px"), ("text-anchor", "middle")];
                     for (nm, val) in atr {
@@ -938,17 +1674,36 @@ impl Sfen {
 
                 let mut gt = Tag::new("g");
                 gt.newattrib("id", "gtegoma");
-                gt.newattrib("transform", "translate(9,75)");
+                gt.newattrib(
+                    "transform",
+                    if flip {
+                        "translate(239,75)"
+                    } else {
+                        "translate(9,75)"
+                    },
+                );
+                gt.newattrib("fill", &theme.gote);
                 let mut tt = Tag::new("g");
                 tt.newattrib("transform", "translate(0,-7)");
                 let mut poly = Tag::new("polygon");
                 poly.newattrib("points", "0,-5 4,-4 5,5 -5,5 -4,-4");
                 poly.newattrib("fill", "none");
-                poly.newattrib("stroke", "black");
+                poly.newattrib("stroke", &theme.fg);
                 tt.addchild(poly);
+                let mut dropped = match drop {
+                    Some((Teban::Gote, koma)) => Some(koma),
+                    _ => None,
+                };
+                if dropped.is_some() && !gotegoma.iter().any(|t| Some(t.koma) == dropped) {
+                    gt.addchild(self.build_lastmovehand(-1, theme));
+                }
                 gt.addchild(tt);
                 let mut y = 20;
                 for tgm in gotegoma {
+                    if dropped == Some(tgm.koma) {
+                        gt.addchild(self.build_lastmovehand(y, theme));
+                        dropped = None;
+                    }
                     let mut tag = Tag::new("text");
                     let atr = [("x", "0"), ("font-size", "16px"), ("text-anchor", "middle")];
                     for (nm, val) in atr {
@@ -978,114 +1733,101 @@ impl Sfen {
 
     /// build svg tag about sente's name.
     ///
-    /// # Argument
+    /// # Arguments
     /// * `name` - sente's name.
+    /// * `flip` - seen from gote. put at the top.
+    /// * `theme` - colors.
     /// # Return value
     /// SVG tag.
-    fn build_sentename(&self, name: Option<String>) -> Tag {
-        let mut gs = Tag::new("g");
-        gs.newattrib("id", "sname");
-        gs.newattrib("transform", "translate(5,250)");
-        let mut gp = Tag::new("g");
-        gp.newattrib("transform", "translate(230,0)");
-        let mut pl = Tag::new("polygon");
-        let atr = [
-            ("points", "10,0 18,2 20,20 0,20 2,2"),
-            ("fill", "black"),
-            ("stroke", "black"),
-            ("stroke-width", "1"),
-        ];
-        for (nm, val) in atr {
-            pl.newattrib(nm, val);
-        }
-        gp.addchild(pl);
-        gs.addchild(gp);
-
-        if name.is_none() {
-            return gs;
-        }
-        let nm = name.unwrap();
-        if nm.is_empty() {
-            return gs;
-        }
-
-        let mut txt = Tag::new("text");
-        let atr = [
-            ("x", "0"),
-            ("y", "15"),
-            ("font-size", "16px"),
-            ("text-anchor", "left"),
-            ("width", "230px"),
-            ("text-overflow", "ellipsis"),
-            ("fill", "black"),
-        ];
-        for (nm, val) in atr {
-            txt.newattrib(nm, val);
-        }
-        txt.value = nm;
-        gs.addchild(txt);
-        gs
+    fn build_sentename(&self, name: Option<String>, flip: bool, theme: &Theme) -> Tag {
+        self.build_name("sname", name, &theme.fg, !flip, theme)
     }
 
     /// build svg tag about gote's name.
     ///
-    /// # Argument
+    /// # Arguments
     /// * `name` - gote's name.
+    /// * `flip` - seen from gote. put at the bottom.
+    /// * `theme` - colors.
+    /// # Return value
+    /// SVG tag.
+    fn build_gotename(&self, name: Option<String>, flip: bool, theme: &Theme) -> Tag {
+        self.build_name("gname", name, "none", flip, theme)
+    }
+
+    /// build svg tag about a player's name.
+    ///
+    /// # Arguments
+    /// * `id` - id of the tag.
+    /// * `name` - player's name.
+    /// * `fill` - color of the mark. foreground for sente, none for gote.
+    /// * `bottom` - put at the bottom or the top.
+    /// * `theme` - colors.
     /// # Return value
     /// SVG tag.
-    fn build_gotename(&self, name: Option<String>) -> Tag {
-        let mut gg = Tag::new("g");
-        gg.newattrib("id", "gname");
-        gg.newattrib("transform", "translate(5,25)");
+    fn build_name(
+        &self,
+        id: &str,
+        name: Option<String>,
+        fill: &str,
+        bottom: bool,
+        theme: &Theme,
+    ) -> Tag {
+        let mut gs = Tag::new("g");
+        gs.newattrib("id", id);
         let mut pl = Tag::new("polygon");
         let atr = [
             ("points", "10,0 18,2 20,20 0,20 2,2"),
-            ("fill", "none"),
-            ("stroke", "black"),
+            ("fill", fill),
+            ("stroke", theme.fg.as_str()),
             ("stroke-width", "1"),
         ];
         for (nm, val) in atr {
             pl.newattrib(nm, val);
         }
-        gg.addchild(pl);
-
-        if name.is_none() {
-            return gg;
-        }
-        let nm = name.unwrap();
-        if nm.is_empty() {
-            return gg;
+        if bottom {
+            gs.newattrib("transform", "translate(5,250)");
+            let mut gp = Tag::new("g");
+            gp.newattrib("transform", "translate(230,0)");
+            gp.addchild(pl);
+            gs.addchild(gp);
+        } else {
+            gs.newattrib("transform", "translate(5,25)");
+            gs.addchild(pl);
         }
 
+        let nm = match name {
+            Some(nm) if !nm.is_empty() => nm,
+            _ => return gs,
+        };
+
         let mut txt = Tag::new("text");
         let atr = [
-            ("x", "25"),
+            ("x", if bottom { "0" } else { "25" }),
             ("y", "15"),
             ("font-size", "16px"),
             ("text-anchor", "left"),
             ("width", "230px"),
             ("text-overflow", "ellipsis"),
-            ("fill", "black"),
+            ("fill", theme.fg.as_str()),
         ];
         for (nm, val) in atr {
             txt.newattrib(nm, val);
         }
         txt.value = nm;
-        gg.addchild(txt);
-        gg
+        gs.addchild(txt);
+        gs
     }
 
     /// build svg tag for title.
-    /// 
+    ///
     /// # Argument
     /// * 'title' - title.
+    /// * `theme` - colors.
     /// # Return value
     /// SVG Tag.
-    fn build_title(&self, title: Option<String>) -> Option<Tag> {
-        if title.is_none() {
-            return None;
-        }
-        let ttl = title.unwrap();
+    fn build_title(&self, title: Option<String>, theme: &Theme) -> Option<Tag> {
+        let ttl = title?;
         if ttl.is_empty() {
             return None;
         }
@@ -1100,7 +1842,7 @@ impl Sfen {
             ("text-anchor", "middle"),
             ("width", "260px"),
             ("text-overflow", "ellipsis"),
-            ("fill", "black"),
+            ("fill", theme.fg.as_str()),
         ];
         for (nm, val) in atr {
             txt.newattrib(nm, val);
@@ -1111,12 +1853,27 @@ impl Sfen {
     }
 
     /// build svg tag to show turn.
-    /// 
+    ///
     /// # Argument
     /// * `teban` - turn.
+    /// * `flip` - seen from gote.
+    /// * `theme` - colors.
     /// # Return value
     /// SVG Tag.
-    fn build_teban(&self, teban: String) -> Option<Tag> {
+    fn build_teban(&self, teban: String, flip: bool, theme: &Theme) -> Option<Tag> {
+        // the mark for sente is put at gote's place and vice versa.
+        let teban = match (flip, teban.as_str()) {
+            (true, "b") => String::from("w"),
+            (true, "w") => String::from("b"),
+            (true, "fb") => String::from("fw"),
+            (true, "fw") => String::from("fb"),
+            _ => teban,
+        };
+        let (drawsente, drawgote) = if flip {
+            ("drawgote", "drawsente")
+        } else {
+            ("drawsente", "drawgote")
+        };
         let mut gt = Tag::new("g");
         gt.newattrib("id", "teban");
 
@@ -1125,12 +1882,12 @@ impl Sfen {
             ("y", "0"),
             ("width", "30"),
             ("height", "30"),
-            ("fill", "#F3C"),
+            ("fill", theme.marker.as_str()),
             ("stroke", "none"),
         ];
         let polyatb = [
             ("points", "15,0 22.5,5 30,0 30,30 0,30 0,0 7.5,5"),
-            ("fill", "#F3C"),
+            ("fill", theme.marker.as_str()),
             ("stroke", "none"),
         ];
         if teban == "w" {
@@ -1168,11 +1925,11 @@ impl Sfen {
                 // ("y", "0"),
                 ("width", "30"),
                 ("height", "5"),
-                ("fill", "#F3C"),
+                ("fill", theme.marker.as_str()),
                 ("stroke", "none"),
             ];
             let mut gs = Tag::new("g");
-            gs.newattrib("id", "drawsente");
+            gs.newattrib("id", drawsente);
             gs.newattrib("transform", "translate(30,20)");
             let mut mark = Tag::new("rect");
             for (nm, val) in rectatb {
@@ -1192,7 +1949,7 @@ impl Sfen {
             gt.addchild(gs);
 
             let mut gg = Tag::new("g");
-            gg.newattrib("id", "drawgote");
+            gg.newattrib("id", drawgote);
             gg.newattrib("transform", "translate(0,245)");
             let mut mark = Tag::new("rect");
             for (nm, val) in rectatb {
@@ -1216,44 +1973,85 @@ impl Sfen {
         Some(gt)
     }
 
+    /// build svg tag for the background.
+    ///
+    /// # Argument
+    /// * `theme` - colors.
+    /// # Return value
+    /// SVG Tag.
+    fn build_background(&self, theme: &Theme) -> Tag {
+        let mut rect = Tag::new("rect");
+        rect.newattrib("id", "background");
+        rect.newattrib("x", "0");
+        rect.newattrib("y", "0");
+        rect.addattrib(Attrib::new("width", svgbuilder::WIDTH.to_string()));
+        rect.addattrib(Attrib::new("height", svgbuilder::HEIGHT.to_string()));
+        rect.newattrib("fill", &theme.bg);
+        rect
+    }
+
     /// make SVG.
-    /// 
+    ///
     /// # Argument
-    /// * `lastmove` - cell index to highlight.
+    /// * `lastmove` - last move to highlight and to put w/ the title if `deco.caption`.
+    /// * `deco` - arrows and so on to draw over the board.
     /// * `turn` - turn.
     /// * `sname` - sente's name.
     /// * `gname` - gote's name.
     /// * `title` - title.
     /// # Return value
-    /// SVG.
+    /// SVG or SfenError.
     pub fn to_svg(
         &self,
-        lastmove: Option<(usize, usize)>,
+        lastmove: &LastMove,
+        deco: &Decoration,
         turn: Option<String>,
         sname: Option<String>,
         gname: Option<String>,
         title: Option<String>,
-    ) -> Result<SVG, String> {
+    ) -> Result<SVG, SfenError> {
         let mut top = Tag::new("g");
-        let ttl = self.build_title(title);
-        if ttl.is_some() {
-            top.addchild(ttl.unwrap());
-        }
-        if turn.is_some() {
-            let tbn = self.build_teban(turn.unwrap());
-            if tbn.is_some() {
-                top.addchild(tbn.unwrap());
+        let caption = if deco.caption {
+            lastmove.to_string().unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let title = match title {
+            Some(ttl) if !ttl.is_empty() && !caption.is_empty() => {
+                Some(format!("{}　{}", ttl, caption))
             }
+            Some(ttl) if !ttl.is_empty() => Some(ttl),
+            _ if !caption.is_empty() => Some(caption),
+            _ => None,
+        };
+        if let Some(ttl) = self.build_title(title, &deco.theme) {
+            top.addchild(ttl);
         }
-        top.addchild(self.build_sentename(sname));
-        top.addchild(self.build_gotename(gname));
-        match self.buildboard(lastmove) {
+        if let Some(tbn) = turn.and_then(|t| self.build_teban(t, deco.flip, &deco.theme)) {
+            top.addchild(tbn);
+        }
+        top.addchild(self.build_sentename(sname, deco.flip, &deco.theme));
+        top.addchild(self.build_gotename(gname, deco.flip, &deco.theme));
+        match self.buildboard(lastmove, deco) {
             Ok(tag) => {
                 top.addchild(tag);
             }
             Err(msg) => return Err(msg),
         }
-        match self.buildtegoma() {
+        let drop = if lastmove.is_ok() && lastmove.is_from_komadai() && !lastmove.koma.is_blank() {
+            let teban = match lastmove.teban {
+                Teban::None => match self.teban() {
+                    Teban::Sente => Teban::Gote,
+                    Teban::Gote => Teban::Sente,
+                    Teban::None => Teban::None,
+                },
+                tbn => tbn,
+            };
+            Some((teban, lastmove.koma.komatype()))
+        } else {
+            None
+        };
+        match self.buildtegoma(drop, deco.flip, &deco.theme) {
             Ok((st, gt)) => {
                 top.addchild(st);
                 top.addchild(gt);
@@ -1261,20 +2059,450 @@ impl Sfen {
             Err(msg) => return Err(msg),
         }
         let mut svg = SVG::new();
+        if !deco.transparent {
+            svg.tag.addchild(self.build_background(&deco.theme));
+        }
         svg.tag.addchild(top);
         Ok(svg)
     }
 }
 
+#[test]
+fn sfenerrortest() {
+    let sfen = Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1");
+    assert!(sfen.is_ok());
+    let sfen = Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b 2P10p 1");
+    let (sentegoma, gotegoma) = sfen.unwrap().extracttegoma().unwrap();
+    assert_eq!(sentegoma[0].num, 2);
+    assert_eq!(gotegoma[0].num, 10);
+
+    assert_eq!(
+        Sfen::new("9/9 b -").err().unwrap(),
+        SfenError::Sfen {
+            found: 3,
+            expected: "4 fields, board turn hands move-number"
+        }
+    );
+    match Sfen::new("lnsgkgsnl/1r5b1/ppppxpppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1") {
+        Err(SfenError::Board {
+            dan, suji, offset, ..
+        }) => assert_eq!((dan, suji, offset), (3, 5, 20)),
+        _ => panic!(),
+    }
+    match Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSN+ b - 1") {
+        Err(SfenError::Board {
+            dan,
+            suji,
+            offset,
+            expected,
+            ..
+        }) => {
+            assert_eq!((dan, suji, offset), (9, 1, 57));
+            assert_eq!(expected, "a piece after '+'");
+        }
+        _ => panic!(),
+    }
+    assert!(matches!(
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL x - 1"),
+        Err(SfenError::Turn { .. })
+    ));
+    assert!(matches!(
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b 2Px 1"),
+        Err(SfenError::Hands { offset: 2, .. })
+    ));
+    assert!(matches!(
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b 2 1"),
+        Err(SfenError::Hands { offset: 1, .. })
+    ));
+    assert!(matches!(
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - one"),
+        Err(SfenError::MoveNumber { .. })
+    ));
+    assert!(matches!(
+        LastMove::read("7776XX"),
+        Err(SfenError::LastMove { offset: 4, .. })
+    ));
+    assert!(matches!(
+        LastMove::read("776"),
+        Err(SfenError::LastMove { offset: 0, .. })
+    ));
+}
+
+#[test]
+fn validatetest() {
+    let sfen =
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1").unwrap();
+    assert!(sfen.validate().unwrap().is_empty());
+    let sfen = Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSN b - 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![Violation::Cells { dan: 9, found: 8 }]
+    );
+    let sfen = Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1 b - 1").unwrap();
+    assert_eq!(sfen.validate().unwrap(), vec![Violation::Rows { found: 8 }]);
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4/9 b - 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![Violation::Rows { found: 10 }]
+    );
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4/p8/9 b - 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![Violation::Rows { found: 11 }]
+    );
+    let sfen = Sfen::new("4k4/9/9/9/9/4P4/4P4/9/4K4 b - 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![Violation::Nifu {
+            suji: 5,
+            teban: Teban::Sente
+        }]
+    );
+    let sfen = Sfen::new("N3k4/9/9/9/9/9/4+P4/4P4/4K3p b - 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![
+            Violation::DeadPiece {
+                suji: 9,
+                dan: 1,
+                koma: String::from("N")
+            },
+            Violation::DeadPiece {
+                suji: 1,
+                dan: 9,
+                koma: String::from("p")
+            },
+        ]
+    );
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 b 18Pp 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![Violation::PieceCount {
+            koma: String::from("P"),
+            found: 19,
+            max: 18
+        }]
+    );
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/3KKK3 b - 1").unwrap();
+    assert_eq!(
+        sfen.validate().unwrap(),
+        vec![
+            Violation::PieceCount {
+                koma: String::from("K"),
+                found: 4,
+                max: 2
+            },
+            Violation::Kings {
+                teban: Teban::Sente,
+                found: 3
+            },
+        ]
+    );
+}
+
+#[test]
+fn lastmovesvgtest() {
+    let lmarrow = Decoration {
+        lmarrow: true,
+        ..Default::default()
+    };
+    let sfen =
+        Sfen::new("lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2").unwrap();
+    let lm = LastMove::read("7776FU").unwrap();
+    let svg = sfen
+        .to_svg(&lm, &Decoration::default(), None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(svg.contains("<g id=\"lastmove\" transform=\"translate(40, 100)\">"));
+    assert!(svg.contains("<g id=\"lastmovefrom\" transform=\"translate(40, 120)\">"));
+    assert!(!svg.contains("lastmovearrow"));
+    let svg = sfen
+        .to_svg(&lm, &lmarrow, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(svg.contains("id=\"lastmovearrow\""));
+    assert!(svg.contains("x1=\"50.0\" y1=\"130.0\""));
+
+    // the dropped piece in hand is highlighted.
+    let sfen = Sfen::new("4k4/9/9/9/4B4/9/9/9/4K4 w Bg 2").unwrap();
+    let lm = LastMove::read("0055KA").unwrap();
+    let (st, gt) = sfen
+        .buildtegoma(Some((Teban::Sente, KomaType::Kaku)), false, &Theme::light())
+        .unwrap();
+    assert!(st
+        .to_svg("")
+        .contains("<rect id=\"lastmovefrom\" x=\"-10\""));
+    assert!(!gt.to_svg("").contains("lastmovefrom"));
+    let svg = sfen
+        .to_svg(&lm, &lmarrow, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(svg.contains(
+        "<rect id=\"lastmovefrom\" x=\"-10\" width=\"20\" height=\"20\" fill=\"#FFB\" y=\"4\"/>"
+    ));
+    assert!(!svg.contains("lastmovearrow"));
+    // the mark is highlighted if no more pieces of the type are left.
+    let (st, _) = sfen
+        .buildtegoma(
+            Some((Teban::Sente, KomaType::Hisha)),
+            false,
+            &Theme::light(),
+        )
+        .unwrap();
+    assert!(st.to_svg("").contains("y=\"-17\""));
+}
+
+#[test]
+fn arrowsvgtest() {
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 w 2Pr 2").unwrap();
+    let deco = Decoration {
+        arrows: decoration::read_arrows("5i5h:red,P*5e:blue,R*5b", &theme::Theme::light().arrow)
+            .unwrap(),
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(&LastMove::new(), &deco, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(svg.contains("<g id=\"arrows\">"));
+    // on the board.
+    assert!(svg.contains("x1=\"90.0\" y1=\"170.0\""));
+    assert!(svg.contains("stroke=\"red\""));
+    // from gote's hand as gote is to move.
+    assert!(svg.contains("x1=\"-26.0\" y1=\"3.0\""));
+    assert!(svg.contains("fill=\"blue\""));
+    assert!(svg.contains("x1=\"-26.0\" y1=\"24.0\""));
+}
+
+#[test]
+fn annotationsvgtest() {
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap();
+    let deco = Decoration {
+        annotations: decoration::read_annotations("55:×@c,44:1,33:⑳@bl").unwrap(),
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(&LastMove::new(), &deco, None, None, None, None)
+        .unwrap()
+        .to_string();
+    // over the pieces.
+    assert!(svg.find("<g id=\"dan9\"").unwrap() < svg.find("<g id=\"annotations\"").unwrap());
+    assert!(
+        svg.contains("<text x=\"90\" y=\"95\" font-size=\"16px\" text-anchor=\"middle\">×</text>")
+    );
+    assert!(svg.contains("<text x=\"119\" y=\"68\" font-size=\"9px\" text-anchor=\"end\">1</text>"));
+    assert!(
+        svg.contains("<text x=\"121\" y=\"59\" font-size=\"9px\" text-anchor=\"start\">⑳</text>")
+    );
+}
+
+#[test]
+fn flipsvgtest() {
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/3K4p b Rg 1").unwrap();
+    let lm = LastMove::read("6958OU").unwrap();
+    let deco = Decoration {
+        marks: decoration::read_marks("19", &theme::Theme::light().mark).unwrap(),
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(
+            &lm,
+            &deco,
+            Some(String::from("b")),
+            Some(String::from("sente")),
+            Some(String::from("gote")),
+            None,
+        )
+        .unwrap()
+        .to_string();
+    let deco = Decoration { flip: true, ..deco };
+    let flipped = sfen
+        .to_svg(
+            &lm,
+            &deco,
+            Some(String::from("b")),
+            Some(String::from("sente")),
+            Some(String::from("gote")),
+            None,
+        )
+        .unwrap()
+        .to_string();
+    // highlights stay on the same cells.
+    assert!(svg.contains("<g id=\"lastmove\" transform=\"translate(80, 140)\">"));
+    assert!(flipped.contains("<g id=\"lastmove\" transform=\"translate(80, 20)\">"));
+    assert!(svg.contains("<g id=\"lastmovefrom\" transform=\"translate(60, 160)\">"));
+    assert!(flipped.contains("<g id=\"lastmovefrom\" transform=\"translate(100, 0)\">"));
+    assert!(svg.contains("<rect x=\"160\" y=\"160\""));
+    assert!(flipped.contains("<rect x=\"0\" y=\"0\""));
+    // sente's king is upside down and at the top.
+    let compact: String = flipped.lines().map(str::trim).collect();
+    assert!(compact.contains(
+        "<g id=\"dan9\" transform=\"translate(0,0)\"><g transform=\"translate(100,0)\"><g transform=\"translate(10,10) rotate(180)\">"
+    ));
+    // gote's pawn on 19 is upright at the top left.
+    assert!(compact.contains(
+        "<g transform=\"translate(0,0)\"><text font-size=\"18px\" text-anchor=\"middle\" fill=\"black\" x=\"10\" y=\"17\">歩</text>"
+    ));
+    // numbers are reversed.
+    assert!(flipped.contains(">１</text>"));
+    assert!(flipped.find(">１</text>").unwrap() < flipped.find(">９</text>").unwrap());
+    assert!(flipped.find(">九</text>").unwrap() < flipped.find(">一</text>").unwrap());
+    // hands, names and the turn are swapped.
+    assert!(svg.contains("<g id=\"stegoma\" transform=\"translate(239,75)\""));
+    assert!(flipped.contains("<g id=\"stegoma\" transform=\"translate(9,75)\""));
+    assert!(flipped.contains("<g id=\"gtegoma\" transform=\"translate(239,75)\""));
+    assert!(svg.contains("<g id=\"sname\" transform=\"translate(5,250)\">"));
+    assert!(flipped.contains("<g id=\"sname\" transform=\"translate(5,25)\">"));
+    assert!(flipped.contains("<g id=\"gname\" transform=\"translate(5,250)\">"));
+    assert!(svg.contains("<g id=\"teban\" transform=\"translate(230,245)\">"));
+    assert!(flipped.contains("<g id=\"teban\" transform=\"translate(0,20)\">"));
+
+    // rows out of the board don't break flipping.
+    for board in ["4k4/9/9/9/9/9/9/9/4K4/9", "4k4/9/9/9/9/9/9/9/4K4/p8/9"] {
+        let sfen = Sfen::new(&format!("{} b - 1", board)).unwrap();
+        let flipped = sfen
+            .to_svg(&LastMove::new(), &deco, None, None, None, None)
+            .unwrap()
+            .to_string();
+        assert!(flipped.contains("<g id=\"dan1\" transform=\"translate(0,160)\">"));
+    }
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4/p8/9 b - 1").unwrap();
+    let flipped = sfen
+        .to_svg(&LastMove::new(), &deco, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(flipped.contains("<g id=\"dan10\" transform=\"translate(0,-20)\">"));
+}
+
+#[test]
+fn themesvgtest() {
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 b G 1").unwrap();
+    let lm = LastMove::read("5958OU").unwrap();
+    let deco = Decoration {
+        theme: Theme::dark(),
+        caption: true,
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(
+            &lm,
+            &deco,
+            Some(String::from("b")),
+            Some(String::from("blackbird")),
+            None,
+            Some(String::from("black box")),
+        )
+        .unwrap()
+        .to_string();
+    // names and titles are left as they are.
+    assert!(svg.contains(">blackbird</text>"));
+    assert!(svg.contains(">black box　５八玉まで</text>"));
+    let nocaption = Decoration {
+        theme: Theme::dark(),
+        ..Default::default()
+    };
+    let title = Some(String::from("black box"));
+    let plain = sfen
+        .to_svg(&lm, &nocaption, None, None, None, title)
+        .unwrap()
+        .to_string();
+    assert!(plain.contains(">black box</text>"));
+    let untitled = sfen
+        .to_svg(&lm, &nocaption, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(!untitled.contains("まで"));
+    assert_eq!(svg.matches("\"black\"").count(), 1);
+    assert!(svg.contains(
+        "<rect id=\"background\" x=\"0\" y=\"0\" width=\"260\" height=\"275\" fill=\"black\"/>"
+    ));
+    assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"20\" height=\"20\" fill=\"#663\"/>"));
+    assert!(svg.contains("fill=\"#C39\""));
+    assert!(svg.contains("<g id=\"stegoma\" transform=\"translate(239,75)\" fill=\"white\">"));
+
+    let deco = Decoration {
+        theme: Theme::wood(),
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(&lm, &deco, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(svg.contains("fill=\"#E8C47C\" stroke=\"#321\""));
+    assert!(svg.contains(
+        "<rect id=\"background\" x=\"0\" y=\"0\" width=\"260\" height=\"275\" fill=\"#FFF8E8\"/>"
+    ));
+
+    // arrows and annotations follow the theme.
+    let deco = Decoration {
+        theme: Theme::printmono(),
+        lmarrow: true,
+        annotations: decoration::read_annotations("55:×").unwrap(),
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(&lm, &deco, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(svg.contains("<g id=\"annotations\" fill=\"black\">"));
+    assert!(!svg.contains("#F80") && !svg.contains("#D00"));
+    assert!(svg.contains("#666"));
+
+    let deco = Decoration {
+        transparent: true,
+        ..deco
+    };
+    let svg = sfen
+        .to_svg(&lm, &deco, None, None, None, None)
+        .unwrap()
+        .to_string();
+    assert!(!svg.contains("background"));
+}
+
+#[test]
+fn markssvgtest() {
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap();
+    let deco = Decoration {
+        marks: decoration::read_marks("55,44:#f88,33:#8f8@0.5", &theme::Theme::light().mark)
+            .unwrap(),
+        ..Default::default()
+    };
+    let svg = sfen
+        .to_svg(
+            &LastMove::read("5958OU").unwrap(),
+            &deco,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        .to_string();
+    let marks = svg.find("<g id=\"marks\">").unwrap();
+    assert!(svg.find("<g id=\"lastmove\"").unwrap() < marks);
+    // under the pieces.
+    assert!(marks < svg.find("<g id=\"dan1\"").unwrap());
+    assert!(svg.contains(&format!(
+        "<rect x=\"80\" y=\"80\" width=\"20\" height=\"20\" fill=\"{}\"/>",
+        theme::Theme::light().mark
+    )));
+    assert!(svg.contains("<rect x=\"100\" y=\"60\" width=\"20\" height=\"20\" fill=\"#f88\"/>"));
+    assert!(svg.contains(
+        "<rect x=\"120\" y=\"40\" width=\"20\" height=\"20\" fill=\"#8f8\" fill-opacity=\"0.5\"/>"
+    ));
+}
+
 /// make a tag for a koma at some cell.
 ///
 /// # Arguments
 /// * `koma` - koma to be converted.
 /// * `x` - x coordinate. suji in other word.
 /// * `y` - x coordinate. dan in other word.
+/// * `flip` - seen from gote. gote's pieces are upright.
+/// * `theme` - colors.
 /// # Return value
 /// SVG tag.
-fn komatag(k: &Koma, x: i32, y: i32) -> Option<Tag> {
+fn komatag(k: &Koma, x: i32, y: i32, flip: bool, theme: &Theme) -> Option<Tag> {
     if k.is_blank() {
         return None;
     }
@@ -1288,8 +2516,15 @@ fn komatag(k: &Koma, x: i32, y: i32) -> Option<Tag> {
     let mut tag = Tag::new("text");
     tag.newattrib("font-size", "18px");
     tag.newattrib("text-anchor", "middle");
-    tag.newattrib("fill", "black");
-    if k.is_sente() {
+    tag.newattrib(
+        "fill",
+        if k.is_sente() {
+            &theme.sente
+        } else {
+            &theme.gote
+        },
+    );
+    if k.is_sente() != flip {
         tag.addattrib(Attrib::new("x", format!("{}", 10)));
         tag.addattrib(Attrib::new("y", format!("{}", 17)));
         tag.value = k.to_kstring().unwrap();
@@ -1309,8 +2544,70 @@ fn komatag(k: &Koma, x: i32, y: i32) -> Option<Tag> {
     Some(kt)
 }
 
+/// build an arrow svg tag.
+///
+/// # Arguments
+/// * `from` - x and y coordinates where the arrow starts.
+/// * `to` - x and y coordinates the arrow points.
+/// * `color` - color of the arrow.
+/// # Return value
+/// SVG tag.
+fn arrowtag(from: (f64, f64), to: (f64, f64), color: &str) -> Tag {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len = (dx * dx + dy * dy).sqrt().max(1.0);
+    let (ux, uy) = (dx / len, dy / len);
+    // stop a little before the center not to hide the piece too much.
+    let tip = (to.0 - ux * 4.0, to.1 - uy * 4.0);
+    let base = (tip.0 - ux * 8.0, tip.1 - uy * 8.0);
+
+    let mut ret = Tag::new("g");
+    ret.newattrib("opacity", "0.7");
+    let mut line = Tag::new("line");
+    line.addattrib(Attrib::new("x1", format!("{:.1}", from.0)));
+    line.addattrib(Attrib::new("y1", format!("{:.1}", from.1)));
+    line.addattrib(Attrib::new("x2", format!("{:.1}", base.0)));
+    line.addattrib(Attrib::new("y2", format!("{:.1}", base.1)));
+    line.newattrib("stroke", color);
+    line.newattrib("stroke-width", "3");
+    line.newattrib("stroke-linecap", "round");
+    ret.addchild(line);
+    let mut head = Tag::new("polygon");
+    head.addattrib(Attrib::new(
+        "points",
+        format!(
+            "{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+            tip.0,
+            tip.1,
+            base.0 - uy * 5.0,
+            base.1 + ux * 5.0,
+            base.0 + uy * 5.0,
+            base.1 - ux * 5.0
+        ),
+    ));
+    head.newattrib("fill", color);
+    ret.addchild(head);
+    ret
+}
+
+/// Returns the top left corner of a cell in the board.
+///
+/// # Arguments
+/// * `pos` - cell index.
+/// * `flip` - seen from gote.
+fn cellpos((suji, dan): (usize, usize), flip: bool) -> (usize, usize) {
+    if flip {
+        (suji * 20 - 20, 180 - dan * 20)
+    } else {
+        (180 - suji * 20, dan * 20 - 20)
+    }
+}
+
 /// build board borders.
-fn banborder() -> Tag {
+///
+/// # Argument
+/// * `flip` - seen from gote. numbers are reversed.
+/// * `theme` - colors.
+fn banborder(flip: bool, theme: &Theme) -> Tag {
     let mut ret = Tag::new("g");
     ret.newattrib("id", "ban");
     let mut rect = Tag::new("rect");
@@ -1321,8 +2618,8 @@ fn banborder() -> Tag {
         ("y", "0"),
         ("width", "180"),
         ("height", "180"),
-        ("fill", "none"),
-        ("stroke", "black"),
+        ("fill", theme.board.as_str()),
+        ("stroke", theme.grid.as_str()),
         ("stroke-width", "2"),
     ];
     for (nm, val) in atr {
@@ -1338,7 +2635,7 @@ fn banborder() -> Tag {
             ("width", "180"),
             ("height", "20"),
             ("fill", "none"),
-            ("stroke", "black"),
+            ("stroke", theme.grid.as_str()),
             ("stroke-width", "1"),
         ];
         for (nm, val) in atr {
@@ -1356,7 +2653,7 @@ fn banborder() -> Tag {
             ("width", "20"),
             ("height", "180"),
             ("fill", "none"),
-            ("stroke", "black"),
+            ("stroke", theme.grid.as_str()),
             ("stroke-width", "1"),
         ];
         for (nm, val) in atr {
@@ -1368,12 +2665,17 @@ fn banborder() -> Tag {
     // suji numbers
     let mut suji = Tag::new("g");
     suji.newattrib("transform", "translate(0,-5)");
-    for (i, ch) in "９８７６５４３２１".chars().enumerate() {
+    let sujinum = if flip {
+        "１２３４５６７８９"
+    } else {
+        "９８７６５４３２１"
+    };
+    for (i, ch) in sujinum.chars().enumerate() {
         let atrs = [
             ("y", "0"),
             ("font-size", "10px"),
             ("text-anchor", "middle"),
-            ("fill", "black"),
+            ("fill", theme.fg.as_str()),
         ];
         let mut txt = Tag::new("text");
         for (nm, val) in atrs {
@@ -1388,12 +2690,17 @@ fn banborder() -> Tag {
     // dan numbers
     let mut dan = Tag::new("g");
     dan.newattrib("transform", "translate(183,0)");
-    for (i, ch) in "一二三四五六七八九".chars().enumerate() {
+    let dannum = if flip {
+        "九八七六五四三二一"
+    } else {
+        "一二三四五六七八九"
+    };
+    for (i, ch) in dannum.chars().enumerate() {
         let atrs = [
             ("x", "0"),
             ("font-size", "10px"),
             ("text-anchor", "left"),
-            ("fill", "black"),
+            ("fill", theme.fg.as_str()),
         ];
         let mut txt = Tag::new("text");
         for (nm, val) in atrs {
@@ -1407,10 +2714,31 @@ fn banborder() -> Tag {
     ret
 }
 
+/// Returns turn expression in japanese.
+///
+/// # Argument
+/// * `turn` - b, w, fb, fw or d.
+/// # Return value
+/// expression or None for unknown turn.
+pub fn turnexp(turn: &str) -> Option<&'static str> {
+    match turn {
+        "b" => Some("先手の番です。"),
+        "w" => Some("後手の番です。"),
+        "fb" => Some("先手の勝ちです。"),
+        "fw" => Some("後手の勝ちです。"),
+        "d" => Some("引き分けです。"),
+        _ => None,
+    }
+}
+
+/// `LastMove::from` of a move from a cell which can't be known.
+/// no cell or arrow is drawn for it.
+pub const FROMUNKNOWN: (usize, usize) = (10, 10);
+
 /// Last move
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct LastMove {
-    /// cell index before move.
+    /// cell index before move. (0, 0) for a drop and `FROMUNKNOWN` if it's not known.
     pub from: (usize, usize),
     /// cell index after move.
     pub to: (usize, usize),
@@ -1421,6 +2749,8 @@ pub struct LastMove {
     /// 'R' => "右", 'L' => "左", 'A' => "上", 'U' => "上",
     /// 'H' => "引", 'S' => "下", 'D' => "下", 'Y' => "寄", 'C' => "直",
     pub dir: String,
+    /// who moved. "▲" or "△" is put in front of the move unless `Teban::None`.
+    pub teban: Teban,
 }
 
 impl LastMove {
@@ -1432,34 +2762,43 @@ impl LastMove {
             koma: Koma::from(' ', Promotion::None),
             promote: sfen::Promotion::None,
             dir: String::new(),
+            teban: Teban::None,
         }
     }
-    /// read last move style text like "7776FUPNLRAHCY".
-    /// 
+    /// read last move style text like "7776FUPNLRAHCY" or "76".
+    /// "+7776FU" in CSA and "7g7f" in USI are read w/ the position by
+    /// `csa::lastmove_after` and `position::lastmove_after`.
+    ///
     /// # Argument
     /// * `txt` - last move style. (from)(to)(koma)(dir).
     /// # Return value
-    /// LastMove or error message.
-    pub fn read(txt: &str) -> Result<LastMove, String> {
+    /// LastMove or SfenError.
+    pub fn read(txt: &str) -> Result<LastMove, SfenError> {
         let mut lm = LastMove {
             from: (0, 0),
             to: (0, 0),
             koma: sfen::Koma::from(' ', sfen::Promotion::None),
             promote: sfen::Promotion::None,
             dir: String::new(),
+            teban: Teban::None,
         };
         let refull =
-            regex::Regex::new("(\\d\\d)(\\d\\d)([a-zA-Z][a-zA-Z])([PN]?)([LRAUHSCY]*)").unwrap();
+            regex::Regex::new("^(\\d\\d)(\\d\\d)([a-zA-Z][a-zA-Z])([PN]?)([LRAUHSCY]*)$").unwrap();
         match refull.captures(txt) {
             Some(cap) => {
                 let frm: usize = cap.get(1).map_or("", |s| s.as_str()).parse().unwrap();
                 lm.from = (frm / 10, frm % 10);
                 let to: usize = cap.get(2).map_or("", |s| s.as_str()).parse().unwrap();
                 lm.to = (to / 10, to % 10);
-                match sfen::Koma::fromcsa(cap.get(3).map_or("", |s| s.as_str())) {
+                let koma = cap.get(3).map_or("", |s| s.as_str());
+                match sfen::Koma::fromcsa(koma) {
                     Some(k) => lm.koma = k,
                     None => {
-                        return Err(format!("\"{}\" is invalid lastmove about koma.", txt));
+                        return Err(SfenError::LastMove {
+                            offset: 4,
+                            found: koma.to_string(),
+                            expected: "a piece in CSA format. ex. FU",
+                        });
                     }
                 }
                 match cap.get(4).map_or("", |s| s.as_str()) {
@@ -1485,7 +2824,11 @@ impl LastMove {
                         );
                         Ok(lm)
                     }
-                    None => Err(format!("\"{}\" is invalid lastmove.", txt)),
+                    None => Err(SfenError::LastMove {
+                        offset: 0,
+                        found: txt.to_string(),
+                        expected: "7776FU, +7776FU, 7g7f or 76",
+                    }),
                 }
             }
         }
@@ -1502,6 +2845,16 @@ impl LastMove {
             None
         }
     }
+    /// Returns from-cell index unless the move is from hand.
+    pub fn frompos(&self) -> Option<(usize, usize)> {
+        let onboard =
+            |(suji, dan): (usize, usize)| (1..=9).contains(&suji) && (1..=9).contains(&dan);
+        if self.is_ok() && onboard(self.from) {
+            Some(self.from)
+        } else {
+            None
+        }
+    }
     /// Returns if the move is from hand.
     pub fn is_from_komadai(&self) -> bool {
         self.from.0 == 0 && self.from.1 == 0
@@ -1512,7 +2865,11 @@ impl LastMove {
             return Ok(String::new());
         }
         const INVALID_MSG: &str = "invalid last move.";
-        let mut ret = String::new();
+        let mut ret = String::from(match self.teban {
+            Teban::Sente => "▲",
+            Teban::Gote => "△",
+            Teban::None => "",
+        });
         ret += ["１", "２", "３", "４", "５", "６", "７", "８", "９"][self.to.0 - 1];
         ret += ["一", "二", "三", "四", "五", "六", "七", "八", "九"][self.to.1 - 1];
         match self.koma.to_kstring() {
@@ -1548,6 +2905,57 @@ impl LastMove {
     }
 }
 
+#[test]
+fn dumptest() {
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/4K4/9 w - 2").unwrap();
+    let lm = LastMove::read("5958OU").unwrap();
+    let last = |turn: Option<&str>, lm: LastMove| {
+        sfen.dump("", "", "", lm, turn).map(|txt| {
+            txt.lines()
+                .find(|l| l.starts_with("手数"))
+                .unwrap_or_default()
+                .to_string()
+        })
+    };
+    assert_eq!(
+        last(None, LastMove::new()).unwrap(),
+        "手数＝2　後手の番です。"
+    );
+    assert_eq!(last(None, lm.clone()).unwrap(), "手数＝2　５八玉まで");
+    assert_eq!(last(Some("w"), lm.clone()).unwrap(), "手数＝2　５八玉まで");
+    assert_eq!(
+        last(Some("b"), LastMove::new()).unwrap(),
+        "手数＝2　先手の番です。"
+    );
+    // results are put instead of the last move.
+    assert_eq!(
+        last(Some("fb"), lm.clone()).unwrap(),
+        "手数＝2　先手の勝ちです。"
+    );
+    assert_eq!(
+        last(Some("fw"), lm.clone()).unwrap(),
+        "手数＝2　後手の勝ちです。"
+    );
+    assert_eq!(last(Some("d"), lm).unwrap(), "手数＝2　引き分けです。");
+    assert!(matches!(
+        last(Some("x"), LastMove::new()),
+        Err(SfenError::Turn { .. })
+    ));
+    // a hand w/ more than 18 pieces of a type can't be written.
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/9/4K4 b 19P 1").unwrap();
+    assert!(matches!(
+        sfen.dump("", "", "", LastMove::new(), None),
+        Err(SfenError::Hands { .. })
+    ));
+    let sfen = Sfen::new("4k4/9/9/9/9/9/9/4K4/9 b - 1").unwrap();
+    let mut lm = LastMove::read("0058KI").unwrap();
+    lm.dir = String::from("Q");
+    assert!(matches!(
+        sfen.dump("", "", "", lm, None),
+        Err(SfenError::LastMove { .. })
+    ));
+}
+
 #[test]
 fn lastmovetest() {
     let lm = LastMove::new();
@@ -1638,6 +3046,11 @@ fn lastmovetest() {
     assert!(!lm.is_from_komadai());
     assert_eq!(lm.to_string().unwrap(), String::from("５八金右まで"));
 
+    // CSA and USI need the position.
+    assert!(LastMove::read("+2726FU").is_err());
+    assert!(LastMove::read("7g7f").is_err());
+    assert!(LastMove::read("G*5b").is_err());
+
     let lm = LastMove::read("2212HI").unwrap();
     assert_eq!(lm.from, (2, 2));
     assert_eq!(lm.to, (1, 2));
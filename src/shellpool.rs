@@ -0,0 +1,373 @@
+use super::*;
+use std::{
+    io::Write,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{mpsc, Mutex},
+    time::{Duration, Instant},
+};
+use svg2png::{Svg2PngConfig, Svg2PngError};
+
+/// a long-lived `inkscape --shell` process.
+struct Worker {
+    /// the process.
+    child: Child,
+    /// commands are written here.
+    stdin: ChildStdin,
+    /// output from stdout read in a thread.
+    output: mpsc::Receiver<Vec<u8>>,
+    /// output from stderr read in a thread.
+    errors: mpsc::Receiver<Vec<u8>>,
+}
+
+/// time to wait for stderr of a running worker after the prompt.
+const STDERRWAIT: Duration = Duration::from_millis(50);
+
+/// read a pipe in a thread.
+///
+/// # Argument
+/// * `pipe` - stdout or stderr of a worker.
+/// # Return value
+/// chunks read from the pipe. disconnected at the end.
+fn forward<R: std::io::Read + Send + 'static>(mut pipe: R) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 1024];
+        while let Ok(len) = pipe.read(&mut buf) {
+            if len == 0 || tx.send(buf[..len].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+impl Worker {
+    /// start a worker and wait for the first prompt.
+    ///
+    /// # Arguments
+    /// * `program` - command line of the shell.
+    /// * `timeout` - time limit to start.
+    /// # Return value
+    /// Worker or Svg2PngError.
+    fn spawn(program: &[String], timeout: Duration) -> Result<Worker, Svg2PngError> {
+        let name = program.first().cloned().unwrap_or_default();
+        let mut child = match Command::new(&name)
+            .args(&program[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(msg) => {
+                return Err(Svg2PngError::Spawn {
+                    program: name,
+                    message: msg.to_string(),
+                })
+            }
+        };
+        let stdin = child.stdin.take().unwrap();
+        let output = forward(child.stdout.take().unwrap());
+        let errors = forward(child.stderr.take().unwrap());
+        let mut worker = Worker {
+            child,
+            stdin,
+            output,
+            errors,
+        };
+        worker.prompt(&name, timeout)?;
+        Ok(worker)
+    }
+
+    /// wait for the prompt ">" on its own line.
+    ///
+    /// # Arguments
+    /// * `name` - name of the program for errors.
+    /// * `timeout` - time limit.
+    /// # Return value
+    /// output before the prompt or Svg2PngError.
+    fn prompt(&mut self, name: &str, timeout: Duration) -> Result<String, Svg2PngError> {
+        let deadline = Instant::now() + timeout;
+        let mut out = Vec::new();
+        loop {
+            let rest = deadline.saturating_duration_since(Instant::now());
+            match self.output.recv_timeout(rest) {
+                Ok(buf) => {
+                    out.extend(buf);
+                    if let Some(txt) = beforeprompt(&String::from_utf8_lossy(&out)) {
+                        return Ok(txt.trim().to_string());
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    return Err(Svg2PngError::Timeout {
+                        program: name.to_string(),
+                        seconds: timeout.as_secs_f64(),
+                        stderr: self.messages(&out, svg2png::PIPEGRACE),
+                    });
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    let code = self.child.wait().ok().and_then(|s| s.code());
+                    return Err(Svg2PngError::Exit {
+                        program: name.to_string(),
+                        code,
+                        stderr: self.messages(&out, svg2png::PIPEGRACE),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns messages in stdout and stderr for errors.
+    ///
+    /// # Arguments
+    /// * `out` - output from stdout.
+    /// * `grace` - time to wait for the rest of stderr.
+    /// # Return value
+    /// messages in stdout and then stderr.
+    fn messages(&self, out: &[u8], grace: Duration) -> String {
+        let deadline = Instant::now() + grace;
+        let mut err = Vec::new();
+        loop {
+            let rest = deadline.saturating_duration_since(Instant::now());
+            match self.errors.recv_timeout(rest) {
+                Ok(buf) => err.extend(buf),
+                Err(_) => break,
+            }
+        }
+        [out, &err]
+            .iter()
+            .map(|txt| String::from_utf8_lossy(txt).trim().to_string())
+            .filter(|txt| !txt.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// convert svg to png through temporary files.
+    ///
+    /// # Arguments
+    /// * `name` - name of the program for errors.
+    /// * `svg` - svg image text.
+    /// * `opt` - converter config.
+    /// # Return value
+    /// PNG data or Svg2PngError.
+    fn convert(
+        &mut self,
+        name: &str,
+        svg: &str,
+        opt: &Svg2PngConfig,
+    ) -> Result<Vec<u8>, Svg2PngError> {
+        let ioerr = |msg: std::io::Error| Svg2PngError::Io {
+            program: name.to_string(),
+            message: msg.to_string(),
+            stderr: String::new(),
+        };
+        let input = svg2png::temppath("svg").map_err(ioerr)?;
+        let output = svg2png::temppath("png").map_err(ioerr)?;
+        svg2png::writetemp(&input, svg.as_bytes()).map_err(ioerr)?;
+
+        let mut cmd = format!(
+            "file-open:{}; export-filename:{}; export-type:png;",
+            input.display(),
+            output.display()
+        );
+        if let Some(bgcolor) = &opt.bgcolor {
+            cmd += &format!(
+                " export-background:{}; export-background-opacity:1;",
+                bgcolor
+            );
+        }
+        if let Some((width, height)) = opt.size {
+            cmd += &format!(" export-width:{}; export-height:{};", width, height);
+        }
+        cmd += " export-do; file-close\n";
+
+        // messages of the jobs before.
+        while self.errors.try_recv().is_ok() {}
+        let ret = match self.stdin.write_all(cmd.as_bytes()) {
+            Ok(()) => self
+                .prompt(name, opt.timeout)
+                .and_then(|msg| match std::fs::read(&output) {
+                    Ok(png) if !png.is_empty() => Ok(png),
+                    _ => Err(Svg2PngError::Empty {
+                        program: name.to_string(),
+                        stderr: self.messages(msg.as_bytes(), STDERRWAIT),
+                    }),
+                }),
+            Err(msg) => Err(ioerr(msg)),
+        };
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+        ret
+    }
+
+    /// Returns if the process is still running.
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.stdin.write_all(b"quit\n");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// idle `inkscape --shell` workers.
+/// a worker is started when no one is idle and dead workers are thrown away.
+pub struct ShellPool {
+    /// command line of the shell.
+    program: Vec<String>,
+    /// idle workers.
+    idle: Mutex<Vec<Worker>>,
+}
+
+impl ShellPool {
+    /// Returns ShellPool.
+    ///
+    /// # Argument
+    /// * `program` - command line of the shell. ex. ["inkscape", "--shell"]
+    pub fn new(program: &[&str]) -> ShellPool {
+        ShellPool {
+            program: program.iter().map(|s| s.to_string()).collect(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// convert svg to png w/ an idle worker or a new one.
+    /// the job is tried again w/ a new worker if the worker had died before the job.
+    ///
+    /// # Arguments
+    /// * `svg` - svg image text.
+    /// * `opt` - converter config.
+    /// # Return value
+    /// PNG data or Svg2PngError.
+    pub fn convert(&self, svg: &str, opt: &Svg2PngConfig) -> Result<Vec<u8>, Svg2PngError> {
+        let name = self.program.first().cloned().unwrap_or_default();
+        let idle = self.idle.lock().unwrap().pop();
+        let mut worker = match idle.and_then(|mut w| w.is_alive().then_some(w)) {
+            Some(worker) => worker,
+            None => Worker::spawn(&self.program, opt.timeout)?,
+        };
+        let ret = match worker.convert(&name, svg, opt) {
+            Err(Svg2PngError::Io { .. }) => {
+                warn!("{} died. restarting...", name);
+                worker = Worker::spawn(&self.program, opt.timeout)?;
+                worker.convert(&name, svg, opt)
+            }
+            ret => ret,
+        };
+        if worker.is_alive() {
+            self.idle.lock().unwrap().push(worker);
+        }
+        ret
+    }
+}
+
+/// workers for svg2png::Type::INKSCAPESHELL.
+static INKSCAPE: once_cell::sync::Lazy<ShellPool> =
+    once_cell::sync::Lazy::new(|| ShellPool::new(&["inkscape", "--shell"]));
+
+/// convert w/ `inkscape --shell` workers kept running.
+///
+/// # Arguments
+/// * `svg` - svg image text.
+/// * `opt` - converter config.
+/// # Return value
+/// PNG data or Svg2PngError.
+pub fn start_inkscape_shell(svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, Svg2PngError> {
+    INKSCAPE.convert(&svg, &opt)
+}
+
+/// Returns the output before the prompt if it ends w/ the prompt.
+/// ">" in the output like "a -> b" is not the prompt.
+///
+/// # Argument
+/// * `txt` - output of the shell.
+/// # Return value
+/// output before the prompt or None.
+fn beforeprompt(txt: &str) -> Option<&str> {
+    let txt = txt.trim_end_matches(' ').strip_suffix('>')?;
+    (txt.is_empty() || txt.ends_with('\n')).then_some(txt)
+}
+
+#[test]
+fn beforeprompttest() {
+    assert_eq!(beforeprompt(">"), Some(""));
+    assert_eq!(beforeprompt("> "), Some(""));
+    assert_eq!(beforeprompt("shell\n> "), Some("shell\n"));
+    assert_eq!(beforeprompt("shell\r\n>"), Some("shell\r\n"));
+    assert_eq!(beforeprompt("a ->"), None);
+    assert_eq!(beforeprompt("a -> b\n"), None);
+    assert_eq!(beforeprompt(""), None);
+}
+
+#[test]
+fn shellpooltest() {
+    // acts like inkscape --shell. writes "png" to export-filename.
+    // "die" in the svg kills the worker during the job.
+    // "arrow" in the svg writes ">" in the output before the png.
+    let script = "printf 'shell\\n>'; while read -r line; do \
+        [ \"$line\" = quit ] && exit 0; \
+        in=$(echo \"$line\" | sed 's/^file-open:\\([^;]*\\);.*/\\1/'); \
+        out=$(echo \"$line\" | sed 's/.*export-filename:\\([^;]*\\);.*/\\1/'); \
+        grep -q hang \"$in\" && sleep 10; \
+        grep -q arrow \"$in\" && printf 'a ->' && sleep 0.2; \
+        grep -q empty \"$in\" && echo 'no png' >&2; \
+        grep -q empty \"$in\" || printf png > \"$out\"; \
+        grep -q die \"$in\" && echo dying >&2 && exit 1; \
+        printf '\\n>'; done";
+    let pool = ShellPool::new(&["sh", "-c", script]);
+    let mut opt = Svg2PngConfig::new();
+    opt.timeout = Duration::from_secs(5);
+    assert_eq!(pool.convert("<svg/>", &opt), Ok(b"png".to_vec()));
+    assert_eq!(pool.idle.lock().unwrap().len(), 1);
+    let pid = pool.idle.lock().unwrap()[0].child.id();
+    // the same worker is used again.
+    assert_eq!(pool.convert("<svg/>", &opt), Ok(b"png".to_vec()));
+    assert_eq!(pool.idle.lock().unwrap()[0].child.id(), pid);
+    // ">" in the output is not the prompt.
+    assert_eq!(pool.convert("arrow", &opt), Ok(b"png".to_vec()));
+    assert_eq!(pool.idle.lock().unwrap()[0].child.id(), pid);
+
+    // stderr is attached to errors.
+    match pool.convert("empty", &opt) {
+        Err(Svg2PngError::Empty { stderr, .. }) => assert_eq!(stderr, "no png"),
+        ret => panic!("{:?}", ret),
+    }
+    // a dead worker is not used any more.
+    match pool.convert("die", &opt) {
+        Err(Svg2PngError::Exit {
+            code: Some(1),
+            stderr,
+            ..
+        }) => assert_eq!(stderr, "dying"),
+        ret => panic!("{:?}", ret),
+    }
+    assert!(pool.idle.lock().unwrap().is_empty());
+    assert_eq!(pool.convert("<svg/>", &opt), Ok(b"png".to_vec()));
+    assert_ne!(pool.idle.lock().unwrap()[0].child.id(), pid);
+    // a worker died while waiting is restarted.
+    let pid = pool.idle.lock().unwrap()[0].child.id();
+    pool.idle.lock().unwrap()[0].child.kill().unwrap();
+    pool.idle.lock().unwrap()[0].child.wait().unwrap();
+    assert_eq!(pool.convert("<svg/>", &opt), Ok(b"png".to_vec()));
+    assert_ne!(pool.idle.lock().unwrap()[0].child.id(), pid);
+
+    opt.timeout = Duration::from_millis(300);
+    assert!(matches!(
+        pool.convert("hang", &opt),
+        Err(Svg2PngError::Timeout { .. })
+    ));
+    assert!(pool.idle.lock().unwrap().is_empty());
+    assert_eq!(pool.convert("<svg/>", &opt), Ok(b"png".to_vec()));
+
+    let pool = ShellPool::new(&["no-such-inkscape", "--shell"]);
+    assert!(matches!(
+        pool.convert("<svg/>", &opt),
+        Err(Svg2PngError::Spawn { .. })
+    ));
+}
@@ -1,113 +1,901 @@
-use std::io::Write;
+use super::*;
+use resvg::{tiny_skia, usvg};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, OpenOptionsExt},
+        process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, UNIX_EPOCH},
+};
 
 /// Converter type.
-#[derive(Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Type {
-    /// use rsvg-converter.
-    RSVG,
-    /// use inkscape v1.1.
-    INKSCAPE11,
+    /// run a command made from a template.
+    COMMAND(converter::Template),
+    /// use resvg in this process.
+    NATIVE,
+    /// use inkscape v1.1 in shell mode kept running.
+    INKSCAPESHELL,
+}
+
+impl Type {
+    /// Returns a converter selected by name.
+    ///
+    /// # Arguments
+    /// * `name` - native, inkscape-shell or a name in `templates`.
+    /// * `templates` - converters defined as command templates.
+    /// # Return value
+    /// Type or None for unknown name.
+    pub fn from(name: &str, templates: &[converter::Template]) -> Option<Type> {
+        match name {
+            "native" => Some(Type::NATIVE),
+            "inkscape-shell" => Some(Type::INKSCAPESHELL),
+            _ => templates
+                .iter()
+                .find(|t| t.name == name)
+                .map(|t| Type::COMMAND(t.clone())),
+        }
+    }
+}
+
+/// serial number for temporary files.
+static SERIAL: AtomicUsize = AtomicUsize::new(0);
+
+/// private directory for temporary files.
+static TEMPDIR: once_cell::sync::OnceCell<PathBuf> = once_cell::sync::OnceCell::new();
+
+/// Returns the directory for temporary files.
+/// it is made only once w/ an unpredictable name and mode 0700.
+fn tempdir() -> std::io::Result<&'static Path> {
+    TEMPDIR
+        .get_or_try_init(|| {
+            for _ in 0..16 {
+                let mut hasher = RandomState::new().build_hasher();
+                let now = std::time::SystemTime::now();
+                hasher.write_u128(now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos()));
+                let dir = std::env::temp_dir().join(format!(
+                    "sfenimageserver-{}-{:016x}",
+                    std::process::id(),
+                    hasher.finish()
+                ));
+                // mkdir does not follow a symlink w/ the same name.
+                match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+                    Ok(()) => return Ok(dir),
+                    Err(msg) if msg.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                    Err(msg) => return Err(msg),
+                }
+            }
+            Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "no directory for temporary files",
+            ))
+        })
+        .map(|dir| dir.as_path())
+}
+
+/// Returns a path for a temporary file in the private directory.
+///
+/// # Argument
+/// * `ext` - extension. ex. svg
+pub fn temppath(ext: &str) -> std::io::Result<PathBuf> {
+    let serial = SERIAL.fetch_add(1, Ordering::SeqCst);
+    Ok(tempdir()?.join(format!("{}.{}", serial, ext)))
+}
+
+/// write a new temporary file. an existing file is not overwritten.
+///
+/// # Arguments
+/// * `path` - path from `temppath`.
+/// * `data` - contents.
+pub fn writetemp(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(data)
+}
+
+/// fonts for Type::NATIVE and the family name to use.
+static FONTS: once_cell::sync::OnceCell<(Arc<usvg::fontdb::Database>, Option<String>)> =
+    once_cell::sync::OnceCell::new();
+
+/// error in converting.
+#[derive(Debug, PartialEq)]
+pub enum Svg2PngError {
+    /// the converter could not be started.
+    Spawn { program: String, message: String },
+    /// svg could not be written or png could not be read.
+    Io {
+        program: String,
+        message: String,
+        stderr: String,
+    },
+    /// the converter was killed as it took too long.
+    Timeout {
+        program: String,
+        seconds: f64,
+        stderr: String,
+    },
+    /// the converter exited w/ an error. `code` is None if killed by a signal.
+    Exit {
+        program: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// the converter exited w/o any output.
+    Empty { program: String, stderr: String },
+    /// svg could not be rendered in this process.
+    Render { message: String },
+    /// too many requests are waiting for converters.
+    Busy { running: usize, waiting: usize },
+}
+
+impl std::fmt::Display for Svg2PngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Svg2PngError::Spawn { program, message } => {
+                write!(
+                    f,
+                    "error running png converter {}... [{}]",
+                    program, message
+                )
+            }
+            Svg2PngError::Io {
+                program,
+                message,
+                stderr,
+            } => write!(
+                f,
+                "error talking to png converter {}... [{}] stderr: [{}]",
+                program, message, stderr
+            ),
+            Svg2PngError::Timeout {
+                program,
+                seconds,
+                stderr,
+            } => write!(
+                f,
+                "png converter {} timed out after {} sec. stderr: [{}]",
+                program, seconds, stderr
+            ),
+            Svg2PngError::Exit {
+                program,
+                code: Some(code),
+                stderr,
+            } => write!(
+                f,
+                "png converter {} exited w/ {}. stderr: [{}]",
+                program, code, stderr
+            ),
+            Svg2PngError::Exit {
+                program,
+                code: None,
+                stderr,
+            } => write!(
+                f,
+                "png converter {} was killed. stderr: [{}]",
+                program, stderr
+            ),
+            Svg2PngError::Empty { program, stderr } => write!(
+                f,
+                "png converter {} returned nothing. stderr: [{}]",
+                program, stderr
+            ),
+            Svg2PngError::Render { message } => write!(f, "error converting svg... [{}]", message),
+            Svg2PngError::Busy { running, waiting } => write!(
+                f,
+                "png converters are busy. {} running, {} waiting.",
+                running, waiting
+            ),
+        }
+    }
 }
 
 /// config for converter.
 pub struct Svg2PngConfig {
     /// Converter type.
     pub typ: Type,
-    /// background color.
-    pub bgcolor: String,
+    /// background color. transparent if None.
+    pub bgcolor: Option<String>,
+    /// width and height of PNG. the size in SVG is used if None.
+    pub size: Option<(u32, u32)>,
+    /// directory of fonts for Type::NATIVE.
+    pub fontdir: String,
+    /// time limit for external converters.
+    pub timeout: Duration,
 }
 
 impl Svg2PngConfig {
-    /// Returns Svg2PngConfig{Type::RSVG, "white"}.
+    /// Returns Svg2PngConfig{rsvg, None, None, "fonts", 10 sec}.
     pub fn new() -> Svg2PngConfig {
         Svg2PngConfig {
-            typ: Type::RSVG,
-            bgcolor: String::from("white"),
+            typ: Type::from("rsvg", &converter::builtins()).unwrap(),
+            bgcolor: None,
+            size: None,
+            fontdir: String::from("fonts"),
+            timeout: Duration::from_secs(10),
         }
     }
 }
 
 /// start converting.
-/// 
+///
 /// # Arguments
 /// * `svg` - svg image text.
 /// * `opt` - converter config.
 /// # Return value
-/// PNG data or error message.
-pub fn start(svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, String> {
-    match opt.typ {
-        Type::RSVG => start_rsvg(svg, opt),
-        Type::INKSCAPE11 => start_inkscape(svg, opt),
+/// PNG data or Svg2PngError.
+pub fn start(svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, Svg2PngError> {
+    match &opt.typ {
+        Type::COMMAND(tmpl) => start_command(tmpl, svg, &opt),
+        Type::NATIVE => start_native(svg, opt),
+        Type::INKSCAPESHELL => shellpool::start_inkscape_shell(svg, opt),
+    }
+}
+
+/// limits the number of converters running at once.
+/// conversions run in the blocking thread pool not to stop async tasks.
+#[derive(Debug)]
+pub struct Limiter {
+    /// permits to run converters.
+    semaphore: Arc<tokio::sync::Semaphore>,
+    /// max number of converters.
+    max: usize,
+    /// max number of requests waiting for a permit.
+    queue: usize,
+    /// number of requests waiting for a permit.
+    waiting: AtomicUsize,
+}
+
+impl Limiter {
+    /// Returns Limiter.
+    ///
+    /// # Arguments
+    /// * `max` - max number of converters running at once.
+    /// * `queue` - max number of requests waiting for converters.
+    pub fn new(max: usize, queue: usize) -> Limiter {
+        Limiter {
+            semaphore: Arc::new(tokio::sync::Semaphore::new(max)),
+            max,
+            queue,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// start converting when a converter is available.
+    ///
+    /// # Arguments
+    /// * `svg` - svg image text.
+    /// * `opt` - converter config.
+    /// # Return value
+    /// PNG data or Svg2PngError. Svg2PngError::Busy if the queue is full.
+    pub async fn start(&self, svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, Svg2PngError> {
+        self.run(move || start(svg, opt)).await
+    }
+
+    /// run `job` in the blocking thread pool when a permit is available.
+    ///
+    /// # Argument
+    /// * `job` - conversion to run.
+    /// # Return value
+    /// result of `job` or Svg2PngError::Busy if the queue is full.
+    async fn run<F>(&self, job: F) -> Result<Vec<u8>, Svg2PngError>
+    where
+        F: FnOnce() -> Result<Vec<u8>, Svg2PngError> + Send + 'static,
+    {
+        let waiting = self.waiting.fetch_add(1, Ordering::SeqCst);
+        // counted out when the permit is given or this future is dropped.
+        let guard = Waiting(&self.waiting);
+        if self.semaphore.available_permits() == 0 && waiting >= self.queue {
+            return Err(Svg2PngError::Busy {
+                running: self.max,
+                waiting,
+            });
+        }
+        let permit = self.semaphore.clone().acquire_owned().await;
+        drop(guard);
+        let permit = match permit {
+            Ok(permit) => permit,
+            Err(msg) => {
+                return Err(Svg2PngError::Render {
+                    message: msg.to_string(),
+                })
+            }
+        };
+        match tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        })
+        .await
+        {
+            Ok(ret) => ret,
+            Err(msg) => Err(Svg2PngError::Render {
+                message: msg.to_string(),
+            }),
+        }
+    }
+}
+
+/// a request waiting for a permit in `Limiter::waiting`.
+/// the count is decremented on drop.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
-/// convert w/ rsvg-convert version 2.50
-/// 
+/// convert w/ a command made from a template.
+///
 /// # Arguments
+/// * `tmpl` - converter.
 /// * `svg` - svg image text.
 /// * `opt` - converter config.
 /// # Return value
-/// PNG data or error message.
-#[allow(dead_code)]
-pub fn start_rsvg(svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, String> {
-    let mut cmd = match std::process::Command::new("rsvg-convert")
-        .arg("--format=png")
-        .arg("-b")
-        .arg(&opt.bgcolor)
-        .stdin(std::process::Stdio::piped())
+/// PNG data or Svg2PngError.
+pub fn start_command(
+    tmpl: &converter::Template,
+    svg: String,
+    opt: &Svg2PngConfig,
+) -> Result<Vec<u8>, Svg2PngError> {
+    let mut values = vec![("format", String::from("png"))];
+    if let Some(bgcolor) = &opt.bgcolor {
+        values.push(("bg", bgcolor.clone()));
+    }
+    if let Some((width, height)) = opt.size {
+        values.push(("width", width.to_string()));
+        values.push(("height", height.to_string()));
+    }
+    let input = tmpl.uses("input").then(|| temppath("svg")).transpose();
+    let output = tmpl.uses("output").then(|| temppath("png")).transpose();
+    let (input, output) = match (input, output) {
+        (Ok(input), Ok(output)) => (input, output),
+        (Err(msg), _) | (_, Err(msg)) => {
+            return Err(Svg2PngError::Io {
+                program: tmpl.name.clone(),
+                message: msg.to_string(),
+                stderr: String::new(),
+            })
+        }
+    };
+    if let Some(input) = &input {
+        values.push(("input", input.display().to_string()));
+    }
+    if let Some(output) = &output {
+        values.push(("output", output.display().to_string()));
+    }
+    let args = tmpl.expand(&values);
+    let mut command = std::process::Command::new(&args[0]);
+    command.args(&args[1..]);
+
+    let ret = match &input {
+        Some(input) => match writetemp(input, svg.as_bytes()) {
+            Ok(()) => run(command, None, output.as_deref(), opt.timeout),
+            Err(msg) => Err(Svg2PngError::Io {
+                program: args[0].clone(),
+                message: msg.to_string(),
+                stderr: String::new(),
+            }),
+        },
+        None => run(command, Some(svg), output.as_deref(), opt.timeout),
+    };
+    for path in input.iter().chain(output.iter()) {
+        let _ = std::fs::remove_file(path);
+    }
+    ret
+}
+
+/// time to wait for the pipes after the converter exits or is killed.
+pub const PIPEGRACE: Duration = Duration::from_millis(500);
+
+/// run `job` in a new thread.
+///
+/// # Argument
+/// * `job` - work to do.
+/// # Return value
+/// receiver of the result of `job`.
+fn background<T, F>(job: F) -> std::sync::mpsc::Receiver<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(job());
+    });
+    rx
+}
+
+/// run a converter. svg is given through stdin and png is read from stdout.
+/// the converter and the processes it started are killed after `timeout`.
+///
+/// # Arguments
+/// * `command` - converter to run.
+/// * `svg` - svg image text. nothing is given through stdin if None.
+/// * `output` - png file written by the converter. stdout is used if None.
+/// * `timeout` - time limit.
+/// # Return value
+/// PNG data or Svg2PngError.
+fn run(
+    mut command: std::process::Command,
+    svg: Option<String>,
+    output: Option<&Path>,
+    timeout: Duration,
+) -> Result<Vec<u8>, Svg2PngError> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let stdin = if svg.is_some() {
+        std::process::Stdio::piped()
+    } else {
+        std::process::Stdio::null()
+    };
+    // a group of its own to kill the processes it started too.
+    let mut child = match command
+        .stdin(stdin)
         .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .process_group(0)
         .spawn()
     {
-        Err(msg) => return Err(format!("error running png converter... [{}]", msg)),
-        Ok(prcs) => prcs,
+        Ok(child) => child,
+        Err(msg) => {
+            return Err(Svg2PngError::Spawn {
+                program,
+                message: msg.to_string(),
+            })
+        }
     };
 
-    match cmd.stdin.as_mut() {
-        None => {
-            return Err(String::from("child_stdin is None."));
-        }
-        Some(child_stdin) => {
-            match child_stdin.write_all(&svg.as_bytes()) {
-                Ok(_) => {
-                    let w = cmd.wait_with_output().unwrap();
-                    // println!("{} bytes.", w.stdout.len());
-                    Ok(w.stdout)
-                }
-                Err(msg) => Err(format!("error running png converter... [{}]", msg)),
+    // read and write in threads not to get stuck w/ full pipes.
+    let writer = child.stdin.take().map(|mut stdin| {
+        let svg = svg.unwrap_or_default();
+        background(move || stdin.write_all(svg.as_bytes()))
+    });
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = background(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let mut stderr = child.stderr.take().unwrap();
+    let errreader = background(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        String::from_utf8_lossy(&buf).trim().to_string()
+    });
+
+    let deadline = Instant::now() + timeout;
+    let kill = |child: &mut std::process::Child| {
+        let _ = std::process::Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", child.id())])
+            .stderr(std::process::Stdio::null())
+            .status();
+        let _ = child.kill();
+        child.wait()
+    };
+    let mut status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = kill(&mut child);
+                break None;
             }
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(_) => break kill(&mut child).ok(),
         }
+    };
+    // the pipes may be kept open by processes out of the group.
+    // give up reading them a little after the deadline.
+    let until = deadline.max(Instant::now()) + PIPEGRACE;
+    fn recv<T>(
+        rx: &std::sync::mpsc::Receiver<T>,
+        until: Instant,
+    ) -> Result<T, std::sync::mpsc::RecvTimeoutError> {
+        rx.recv_timeout(until.saturating_duration_since(Instant::now()))
     }
+    let written = writer
+        .map_or(Ok(Ok(())), |w| recv(&w, until))
+        .unwrap_or(Ok(()));
+    let png = match recv(&reader, until) {
+        Ok(png) => png,
+        Err(_) => {
+            status = None;
+            Ok(Vec::new())
+        }
+    };
+    let stderr = recv(&errreader, until).unwrap_or_default();
+
+    let status = match status {
+        Some(status) => status,
+        None => {
+            return Err(Svg2PngError::Timeout {
+                program,
+                seconds: timeout.as_secs_f64(),
+                stderr,
+            })
+        }
+    };
+    if !status.success() {
+        return Err(Svg2PngError::Exit {
+            program,
+            code: status.code(),
+            stderr,
+        });
+    }
+    if let Err(msg) = written {
+        return Err(Svg2PngError::Io {
+            program,
+            message: msg.to_string(),
+            stderr,
+        });
+    }
+    let png = match output {
+        Some(output) => std::fs::read(output).or_else(|msg| match msg.kind() {
+            std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            _ => Err(msg),
+        }),
+        None => png,
+    };
+    match png {
+        Ok(png) if png.is_empty() => Err(Svg2PngError::Empty { program, stderr }),
+        Ok(png) => Ok(png),
+        Err(msg) => Err(Svg2PngError::Io {
+            program,
+            message: msg.to_string(),
+            stderr,
+        }),
+    }
+}
+
+/// font embedded in the binary. it has no kanji.
+const EMBEDDED_FONT: &[u8] = include_bytes!("../res/DejaVuSans.ttf");
+
+/// Returns fonts for Type::NATIVE.
+/// fonts are loaded only once from `fontdir` in order of file names and the embedded one.
+///
+/// # Argument
+/// * `fontdir` - directory of fonts.
+/// # Return value
+/// fonts and the family name of the first font w/ kanji for pieces,
+/// the first font in `fontdir` or the embedded one.
+fn fonts(fontdir: &str) -> (Arc<usvg::fontdb::Database>, Option<String>) {
+    FONTS
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            let mut files: Vec<PathBuf> = std::fs::read_dir(fontdir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|ent| ent.path())
+                .collect();
+            // not to depend on the order in the directory.
+            files.sort();
+            for data in files.iter().filter_map(|path| std::fs::read(path).ok()) {
+                db.load_font_data(data);
+            }
+            db.load_font_data(EMBEDDED_FONT.to_vec());
+            let kanji = |id| {
+                db.with_face_data(id, |data, index| {
+                    ttf_parser::Face::parse(data, index)
+                        .is_ok_and(|face| face.glyph_index('歩').is_some())
+                })
+                .unwrap_or(false)
+            };
+            let family = db
+                .faces()
+                .find(|face| kanji(face.id))
+                .or_else(|| db.faces().next())
+                .and_then(|face| face.families.first())
+                .map(|(name, _)| name.clone());
+            (Arc::new(db), family)
+        })
+        .clone()
 }
 
-/// convert w/ inkscape version 1.1
+/// convert w/ resvg in this process.
+/// `opt.bgcolor` is not used. draw the background in the svg.
 ///
 /// # Arguments
 /// * `svg` - svg image text.
 /// * `opt` - converter config.
 /// # Return value
-/// PNG data or error message.
-#[allow(dead_code)]
-pub fn start_inkscape(svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, String> {
-    let mut cmd = match std::process::Command::new("inkscape")
-        .arg("--pipe")
-        .arg("--export-filename=-")
-        .arg("--export-type=png")
-        .arg("-b")
-        .arg(&opt.bgcolor)
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-    {
-        Err(msg) => return Err(format!("error running png converter... [{}]", msg)),
-        Ok(prcs) => prcs,
+/// PNG data or Svg2PngError.
+pub fn start_native(svg: String, opt: Svg2PngConfig) -> Result<Vec<u8>, Svg2PngError> {
+    let (fontdb, family) = fonts(&opt.fontdir);
+    let mut uopt = usvg::Options {
+        fontdb,
+        ..Default::default()
     };
-
-    match cmd.stdin.take().unwrap().write_all(&svg.as_bytes()) {
-        Ok(_) => {
-            let w = cmd.wait_with_output().unwrap();
-            // println!("{} bytes.", w.stdout.len());
-            Ok(w.stdout)
+    if let Some(family) = family {
+        uopt.font_family = family;
+    }
+    let tree = match usvg::Tree::from_str(&svg, &uopt) {
+        Ok(tree) => tree,
+        Err(msg) => {
+            return Err(Svg2PngError::Render {
+                message: msg.to_string(),
+            })
         }
-        Err(msg) => Err(format!("error running png converter... [{}]", msg)),
+    };
+    let size = tree.size();
+    let (width, height) = opt
+        .size
+        .unwrap_or((size.width().ceil() as u32, size.height().ceil() as u32));
+    let mut pixmap = match tiny_skia::Pixmap::new(width, height) {
+        Some(pixmap) => pixmap,
+        None => {
+            return Err(Svg2PngError::Render {
+                message: format!("invalid image size. {}x{}", width, height),
+            })
+        }
+    };
+    let transform = tiny_skia::Transform::from_scale(
+        width as f32 / size.width(),
+        height as f32 / size.height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    match pixmap.encode_png() {
+        Ok(png) => Ok(png),
+        Err(msg) => Err(Svg2PngError::Render {
+            message: msg.to_string(),
+        }),
     }
 }
+
+#[test]
+fn nativetest() {
+    let svg =
+        "<svg width='26' height='27' viewBox='0 0 260 275' xmlns='http://www.w3.org/2000/svg'>\
+        <rect width='260' height='275' fill='white'/></svg>";
+    let mut opt = Svg2PngConfig::new();
+    opt.typ = Type::NATIVE;
+    let png = start(svg.to_string(), opt).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+    // IHDR has width and height.
+    assert_eq!(&png[16..24], &[0, 0, 0, 26, 0, 0, 0, 27]);
+
+    let mut opt = Svg2PngConfig::new();
+    opt.typ = Type::NATIVE;
+    opt.size = Some((52, 55));
+    let png = start(svg.to_string(), opt).unwrap();
+    assert_eq!(&png[16..24], &[0, 0, 0, 52, 0, 0, 0, 55]);
+
+    let mut opt = Svg2PngConfig::new();
+    opt.typ = Type::NATIVE;
+    assert!(start(String::from("<svg"), opt).is_err());
+
+    // glyphs of the embedded font are drawn in a cell w/o any fonts in fontdir.
+    let cell = |txt: &str| {
+        format!(
+            "<svg width='20' height='20' viewBox='0 0 20 20' xmlns='http://www.w3.org/2000/svg'>\
+            <rect width='20' height='20' fill='white'/>\
+            <text font-size='18px' text-anchor='middle' fill='black' x='10' y='17'>{}</text></svg>",
+            txt
+        )
+    };
+    let dark = |txt: &str| {
+        let mut opt = Svg2PngConfig::new();
+        opt.typ = Type::NATIVE;
+        opt.fontdir = String::from("no such dir");
+        let png = start(cell(txt), opt).unwrap();
+        let pixmap = tiny_skia::Pixmap::decode_png(&png).unwrap();
+        pixmap
+            .pixels()
+            .iter()
+            .filter(|px| px.red() < 128 && px.alpha() == 255)
+            .count()
+    };
+    assert_eq!(dark(""), 0);
+    assert!(dark("K") > 10);
+    assert!(dark("8") > 10);
+}
+
+#[test]
+fn temptest() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = temppath("svg").unwrap();
+    let dir = path.parent().unwrap();
+    let meta = std::fs::symlink_metadata(dir).unwrap();
+    assert!(meta.is_dir());
+    assert_eq!(meta.permissions().mode() & 0o777, 0o700);
+    assert_ne!(path, temppath("svg").unwrap());
+
+    writetemp(&path, b"svg").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"svg");
+    // existing files and symlinks are not written through.
+    assert!(writetemp(&path, b"oops").is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"svg");
+    let link = temppath("png").unwrap();
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    assert!(writetemp(&link, b"oops").is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"svg");
+    let _ = std::fs::remove_file(&link);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn runtest() {
+    let sh = |script: &str| {
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    };
+    let timeout = Duration::from_secs(5);
+    assert_eq!(
+        run(sh("cat"), Some(String::from("png")), None, timeout),
+        Ok(b"png".to_vec())
+    );
+    assert_eq!(
+        run(
+            sh("cat >/dev/null; echo oops >&2; exit 3"),
+            Some(String::new()),
+            None,
+            timeout
+        ),
+        Err(Svg2PngError::Exit {
+            program: String::from("sh"),
+            code: Some(3),
+            stderr: String::from("oops")
+        })
+    );
+    assert_eq!(
+        run(sh("cat >/dev/null"), Some(String::new()), None, timeout),
+        Err(Svg2PngError::Empty {
+            program: String::from("sh"),
+            stderr: String::new()
+        })
+    );
+    let start = Instant::now();
+    assert!(matches!(
+        run(sh("exec sleep 10"), None, None, Duration::from_millis(200)),
+        Err(Svg2PngError::Timeout { .. })
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    // a grandchild holding stdout is killed w/ the converter.
+    let start = Instant::now();
+    assert!(matches!(
+        run(
+            sh("sleep 10 & exec sleep 10"),
+            None,
+            None,
+            Duration::from_millis(200)
+        ),
+        Err(Svg2PngError::Timeout { .. })
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    // stdout held out of the group is not read any longer.
+    let start = Instant::now();
+    assert!(matches!(
+        run(
+            sh("setsid sleep 10 & exec sleep 10"),
+            None,
+            None,
+            Duration::from_millis(200)
+        ),
+        Err(Svg2PngError::Timeout { .. })
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(matches!(
+        run(
+            sh("setsid sleep 10 & echo png"),
+            None,
+            None,
+            Duration::from_millis(200)
+        ),
+        Err(Svg2PngError::Timeout { .. })
+    ));
+    assert!(matches!(
+        run(
+            std::process::Command::new("no-such-converter"),
+            None,
+            None,
+            timeout
+        ),
+        Err(Svg2PngError::Spawn { .. })
+    ));
+}
+
+#[test]
+fn commandtest() {
+    let mut opt = Svg2PngConfig::new();
+    opt.timeout = Duration::from_secs(5);
+    let cat = converter::Template::read("cat", "cat").unwrap();
+    assert_eq!(
+        start_command(&cat, String::from("svg"), &opt),
+        Ok(b"svg".to_vec())
+    );
+    let cp = converter::Template::read("cp", "cp {input} {output}").unwrap();
+    assert_eq!(
+        start_command(&cp, String::from("svg"), &opt),
+        Ok(b"svg".to_vec())
+    );
+    let args = converter::Template::read("args", "echo -n [{bg}] [{width}x{height}]").unwrap();
+    opt.bgcolor = Some(String::from("rgb(1, 2, 3)"));
+    assert_eq!(
+        start_command(&args, String::new(), &opt),
+        Ok(b"rgb(1, 2, 3)".to_vec())
+    );
+    opt.size = Some((26, 27));
+    assert_eq!(
+        start_command(&args, String::new(), &opt),
+        Ok(b"rgb(1, 2, 3) 26x27".to_vec())
+    );
+    let none = converter::Template::read("none", "true {output}").unwrap();
+    assert!(matches!(
+        start_command(&none, String::new(), &opt),
+        Err(Svg2PngError::Empty { .. })
+    ));
+
+    let defs = converter::builtins();
+    assert!(matches!(
+        Type::from("cairosvg", &defs),
+        Some(Type::COMMAND(t)) if t.name == "cairosvg"
+    ));
+    assert_eq!(Type::from("native", &defs), Some(Type::NATIVE));
+    assert_eq!(
+        Type::from("inkscape-shell", &defs),
+        Some(Type::INKSCAPESHELL)
+    );
+    assert_eq!(Type::from("unknown", &defs), None);
+}
+
+#[test]
+fn limitertest() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let limiter = Arc::new(Limiter::new(1, 1));
+        let slow = || {
+            std::thread::sleep(Duration::from_millis(300));
+            Ok(b"png".to_vec())
+        };
+        let first = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.run(slow).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let second = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.run(slow).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // one running and one waiting.
+        assert_eq!(
+            limiter.run(slow).await,
+            Err(Svg2PngError::Busy {
+                running: 1,
+                waiting: 1
+            })
+        );
+        assert_eq!(first.await.unwrap(), Ok(b"png".to_vec()));
+        assert_eq!(second.await.unwrap(), Ok(b"png".to_vec()));
+        assert_eq!(limiter.run(slow).await, Ok(b"png".to_vec()));
+
+        // a request dropped while waiting does not stay in the queue.
+        let first = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.run(slow).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let pending = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.run(slow).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(limiter.waiting.load(Ordering::SeqCst), 1);
+        pending.abort();
+        assert!(pending.await.unwrap_err().is_cancelled());
+        assert_eq!(limiter.waiting.load(Ordering::SeqCst), 0);
+        assert_eq!(first.await.unwrap(), Ok(b"png".to_vec()));
+        assert_eq!(limiter.waiting.load(Ordering::SeqCst), 0);
+    });
+}
@@ -1,3 +1,6 @@
+use super::*;
+use sfen::SfenError;
+
 /// Attribute in a tag.
 pub struct Attrib {
     /// attribute name.
@@ -15,7 +18,7 @@ impl Attrib {
     pub fn new(nm: &str, val: String) -> Attrib {
         Attrib {
             name: String::from(nm),
-            val: val,
+            val,
         }
     }
     /// Returns Attrib.
@@ -26,12 +29,15 @@ impl Attrib {
     pub fn from(nm: &str, val: &str) -> Attrib {
         Attrib::new(nm, val.to_string())
     }
-    /// Returns "name=value".
-    pub fn to_string(&self) -> String {
+}
+
+impl std::fmt::Display for Attrib {
+    /// writes "name=value".
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         if self.val.is_empty() {
-            format!(" {}", self.name)
+            write!(f, " {}", self.name)
         } else {
-            format!(" {}=\"{}\"", self.name, self.val)
+            write!(f, " {}=\"{}\"", self.name, self.val)
         }
     }
 }
@@ -102,6 +108,17 @@ impl Tag {
     pub fn newattrib(&mut self, nm: &str, val: &str) {
         self.addattrib(Attrib::from(nm, val));
     }
+    /// set an attribute. the value is replaced if it exists.
+    ///
+    /// # Arguments
+    /// * `nm` - attribute name.
+    /// * `val` - attribute value.
+    pub fn setattrib(&mut self, nm: &str, val: String) {
+        match self.attribs.iter_mut().find(|a| a.name == nm) {
+            Some(atr) => atr.val = val,
+            None => self.addattrib(Attrib::new(nm, val)),
+        }
+    }
     /// generate SVG image text.
     ///
     /// # Argument
@@ -109,7 +126,7 @@ impl Tag {
     /// # Return value
     /// SVG image as text.
     pub fn to_svg(&self, indent: &str) -> String {
-        if self.children.len() > 0 {
+        if !self.children.is_empty() {
             format!(
                 "{ind}<{nm}{val}{atr}>\n{chld}{ind}</{nm}>\n",
                 nm = self.name,
@@ -119,7 +136,7 @@ impl Tag {
                     format!(" value=\"{}\"", self.value)
                 },
                 atr = self.attrib2string(),
-                chld = self.child2string(indent.clone()),
+                chld = self.child2string(indent),
                 ind = indent
             )
         } else if self.value.is_empty() {
@@ -207,9 +224,21 @@ fn tagtest() {
         t.to_svg("klm"),
         "klm<tag checkbox=\"on\">\nklm <child/>\nklm</tag>\n"
     );
+
+    t.setattrib("checkbox", String::from("off"));
+    t.setattrib("width", String::from("10"));
+    assert_eq!(t.attrib2string(), " checkbox=\"off\" width=\"10\"");
 }
 
+/// width of the image w/o scaling.
+pub const WIDTH: u32 = 260;
+/// height of the image w/o scaling.
+pub const HEIGHT: u32 = 275;
+/// max width or height of the image.
+pub const MAXSIZE: u32 = 4096;
+
 /// SVG
+#[allow(clippy::upper_case_acronyms)]
 pub struct SVG {
     pub tag: Tag,
 }
@@ -220,20 +249,111 @@ impl SVG {
         let mut svg = SVG {
             tag: Tag::new("svg"),
         };
-        let atb = [
-            ("width", "260"),
-            ("height", "275"),
-            ("viewBox", "0 0 260 275"),
-            ("version", "1.1"),
-            ("xmlns", "http://www.w3.org/2000/svg"),
-        ];
+        svg.tag.addattrib(Attrib::new("width", WIDTH.to_string()));
+        svg.tag.addattrib(Attrib::new("height", HEIGHT.to_string()));
+        svg.tag
+            .addattrib(Attrib::new("viewBox", format!("0 0 {} {}", WIDTH, HEIGHT)));
+        let atb = [("version", "1.1"), ("xmlns", "http://www.w3.org/2000/svg")];
         for (nm, val) in atb {
             svg.tag.newattrib(nm, val);
         }
         svg
     }
-    /// Returns SVG image text.
-    pub fn to_string(&self) -> String {
-        format!("<?xml version='1.0'?>\n{}", self.tag.to_svg(""))
+    /// change the size of the image. the drawing is scaled by viewBox.
+    ///
+    /// # Arguments
+    /// * `width` - width in pixels.
+    /// * `height` - height in pixels.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.tag.setattrib("width", width.to_string());
+        self.tag.setattrib("height", height.to_string());
     }
 }
+
+/// Returns width and height of the image.
+///
+/// # Arguments
+/// * `scale` - scale. can't be used w/ `width` or `height`.
+/// * `width` - width in pixels. the aspect ratio is kept w/o `height`.
+/// * `height` - height in pixels. the aspect ratio is kept w/o `width`.
+/// # Return value
+/// width and height or SfenError.
+pub fn imagesize(
+    scale: Option<f64>,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<(u32, u32), SfenError> {
+    let (w, h) = match (scale, width, height) {
+        (Some(scale), Some(_), _) | (Some(scale), _, Some(_)) => {
+            return Err(SfenError::Param {
+                name: "scale",
+                found: scale.to_string(),
+                expected: "no width or height w/ scale",
+            })
+        }
+        (Some(scale), None, None) if scale > 0.0 => (
+            (WIDTH as f64 * scale).round(),
+            (HEIGHT as f64 * scale).round(),
+        ),
+        (Some(scale), None, None) => {
+            return Err(SfenError::Param {
+                name: "scale",
+                found: scale.to_string(),
+                expected: "a positive number",
+            })
+        }
+        (None, Some(w), Some(h)) => (w as f64, h as f64),
+        (None, Some(w), None) => (w as f64, (w as f64 * HEIGHT as f64 / WIDTH as f64).round()),
+        (None, None, Some(h)) => ((h as f64 * WIDTH as f64 / HEIGHT as f64).round(), h as f64),
+        (None, None, None) => (WIDTH as f64, HEIGHT as f64),
+    };
+    let range = 1.0..=MAXSIZE as f64;
+    if !range.contains(&w) || !range.contains(&h) {
+        // the same as MAXSIZE.
+        return Err(SfenError::Param {
+            name: "size",
+            found: format!("{}x{}", w, h),
+            expected: "width and height from 1 to 4096",
+        });
+    }
+    Ok((w as u32, h as u32))
+}
+
+impl std::fmt::Display for SVG {
+    /// writes SVG image text.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<?xml version='1.0'?>\n{}", self.tag.to_svg(""))
+    }
+}
+
+#[test]
+fn svgsizetest() {
+    let mut svg = SVG::new();
+    assert!(svg
+        .to_string()
+        .contains("<svg width=\"260\" height=\"275\" viewBox=\"0 0 260 275\""));
+    svg.resize(520, 550);
+    assert!(svg
+        .to_string()
+        .contains("<svg width=\"520\" height=\"550\" viewBox=\"0 0 260 275\""));
+
+    assert_eq!(imagesize(None, None, None), Ok((260, 275)));
+    assert_eq!(imagesize(Some(2.0), None, None), Ok((520, 550)));
+    assert_eq!(imagesize(Some(0.5), None, None), Ok((130, 138)));
+    assert_eq!(imagesize(None, Some(1040), None), Ok((1040, 1100)));
+    assert_eq!(imagesize(None, None, Some(550)), Ok((520, 550)));
+    assert_eq!(imagesize(None, Some(300), Some(300)), Ok((300, 300)));
+    assert!(imagesize(Some(2.0), Some(300), None).is_err());
+    assert!(imagesize(Some(0.0), None, None).is_err());
+    assert!(imagesize(Some(100.0), None, None).is_err());
+    assert!(imagesize(None, Some(0), None).is_err());
+    assert!(imagesize(None, Some(5000), None).is_err());
+    assert_eq!(
+        imagesize(Some(-1.0), None, None).unwrap_err().to_string(),
+        "scale: \"-1\", expected a positive number."
+    );
+    assert!(matches!(
+        imagesize(None, Some(5000), None),
+        Err(SfenError::Param { name: "size", .. })
+    ));
+}
@@ -0,0 +1,189 @@
+/// colors to draw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// texts, numbers and marks.
+    pub fg: String,
+    /// fill of the background rect in svg and png. no rect w/ transparent=1.
+    pub bg: String,
+    /// lines on the board.
+    pub grid: String,
+    /// board.
+    pub board: String,
+    /// sente's pieces.
+    pub sente: String,
+    /// gote's pieces.
+    pub gote: String,
+    /// the cell the last move went to.
+    pub lastmove: String,
+    /// the cell the last move came from.
+    pub lastmovefrom: String,
+    /// marks to show the turn.
+    pub marker: String,
+    /// arrows w/o a color and the arrow for the last move.
+    pub arrow: String,
+    /// painted cells w/o a color.
+    pub mark: String,
+    /// glyphs on cells.
+    pub annotation: String,
+}
+
+impl Theme {
+    /// Returns a built-in theme.
+    ///
+    /// # Argument
+    /// * `name` - light, dark, wood or print-mono.
+    /// # Return value
+    /// Theme or None for unknown name.
+    pub fn from(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::light()),
+            "dark" => Some(Theme::dark()),
+            "wood" => Some(Theme::wood()),
+            "print-mono" => Some(Theme::printmono()),
+            _ => None,
+        }
+    }
+
+    /// Returns black on white.
+    pub fn light() -> Theme {
+        Theme {
+            fg: String::from("black"),
+            bg: String::from("white"),
+            grid: String::from("black"),
+            board: String::from("none"),
+            sente: String::from("black"),
+            gote: String::from("black"),
+            lastmove: String::from("#FF4"),
+            lastmovefrom: String::from("#FFB"),
+            marker: String::from("#F3C"),
+            arrow: String::from("#F80"),
+            mark: String::from("#8CF"),
+            annotation: String::from("#D00"),
+        }
+    }
+
+    /// Returns white on black.
+    pub fn dark() -> Theme {
+        Theme {
+            fg: String::from("white"),
+            bg: String::from("black"),
+            grid: String::from("white"),
+            board: String::from("none"),
+            sente: String::from("white"),
+            gote: String::from("white"),
+            lastmove: String::from("#663"),
+            lastmovefrom: String::from("#442"),
+            marker: String::from("#C39"),
+            arrow: String::from("#F90"),
+            mark: String::from("#357"),
+            annotation: String::from("#F55"),
+        }
+    }
+
+    /// Returns a wooden board.
+    pub fn wood() -> Theme {
+        Theme {
+            fg: String::from("#321"),
+            bg: String::from("#FFF8E8"),
+            grid: String::from("#321"),
+            board: String::from("#E8C47C"),
+            sente: String::from("black"),
+            gote: String::from("black"),
+            lastmove: String::from("#F96"),
+            lastmovefrom: String::from("#EB8"),
+            marker: String::from("#C30"),
+            arrow: String::from("#06C"),
+            mark: String::from("#9CF"),
+            annotation: String::from("#B00"),
+        }
+    }
+
+    /// Returns black and grays for printing.
+    pub fn printmono() -> Theme {
+        Theme {
+            fg: String::from("black"),
+            bg: String::from("white"),
+            grid: String::from("black"),
+            board: String::from("none"),
+            sente: String::from("black"),
+            gote: String::from("black"),
+            lastmove: String::from("#CCC"),
+            lastmovefrom: String::from("#EEE"),
+            marker: String::from("#888"),
+            arrow: String::from("#666"),
+            mark: String::from("#DDD"),
+            annotation: String::from("black"),
+        }
+    }
+
+    /// set the color of texts, lines and pieces.
+    ///
+    /// # Argument
+    /// * `color` - color.
+    pub fn set_fg(&mut self, color: &str) {
+        self.fg = color.to_string();
+        self.grid = color.to_string();
+        self.sente = color.to_string();
+        self.gote = color.to_string();
+    }
+}
+
+/// check if `txt` is a CSS color which can be put into SVG safely.
+/// color names, #rgb, #rgba, #rrggbb, #rrggbbaa,
+/// rgb(), rgba(), hsl() and hsla() are accepted.
+pub fn is_color(txt: &str) -> bool {
+    let re = regex::Regex::new(
+        "^([a-zA-Z]{1,32}|#([0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})|\
+        (rgba?|hsla?)\\(\\s*[+-]?[0-9.]+(%|deg)?(\\s*[, /]\\s*[+-]?[0-9.]+%?){2,3}\\s*\\))$",
+    )
+    .unwrap();
+    re.is_match(txt)
+}
+
+impl Default for Theme {
+    /// Returns light theme.
+    fn default() -> Theme {
+        Theme::light()
+    }
+}
+
+#[test]
+fn themetest() {
+    assert_eq!(Theme::from("light"), Some(Theme::light()));
+    assert_eq!(Theme::from("dark").unwrap().fg, "white");
+    assert_eq!(Theme::from("wood").unwrap().board, "#E8C47C");
+    assert_eq!(Theme::from("print-mono").unwrap().lastmove, "#CCC");
+    assert_eq!(Theme::from("print-mono").unwrap().arrow, "#666");
+    assert_eq!(Theme::from("print-mono").unwrap().annotation, "black");
+    assert_eq!(Theme::from("sepia"), None);
+    assert_eq!(Theme::default(), Theme::light());
+
+    let mut theme = Theme::dark();
+    theme.set_fg("red");
+    assert_eq!(theme.fg, "red");
+    assert_eq!(theme.grid, "red");
+    assert_eq!(theme.sente, "red");
+    assert_eq!(theme.gote, "red");
+    assert_eq!(theme.bg, "black");
+}
+
+#[test]
+fn colortest() {
+    assert!(is_color("red"));
+    assert!(is_color("transparent"));
+    assert!(is_color("#FF4"));
+    assert!(is_color("#ff44"));
+    assert!(is_color("#12345678"));
+    assert!(is_color("rgb(255, 0, 0)"));
+    assert!(is_color("rgba(255,0,0,0.5)"));
+    assert!(is_color("rgb(100% 0% 0% / 50%)"));
+    assert!(is_color("hsl(120deg, 50%, 50%)"));
+    assert!(is_color("hsla(120, 50%, 50%, .3)"));
+    assert!(!is_color(""));
+    assert!(!is_color("#12345"));
+    assert!(!is_color("red;"));
+    assert!(!is_color("red\" onload=\"alert(1)"));
+    assert!(!is_color("rgb(1,2)"));
+    assert!(!is_color("rgb(1,2,3))"));
+    assert!(!is_color("url(#x)"));
+}